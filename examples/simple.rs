@@ -1,9 +1,5 @@
 use clip_cpp_rs as clip;
 
-fn score(a: &[f32], b: &[f32]) -> f32 {
-    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
-}
-
 fn main() {
     let model_path = "./models/clip-vit-base-patch32_ggml-model-q4_1.gguf";
     let img_path = "./red_apple.jpg";
@@ -14,17 +10,23 @@ fn main() {
         .expect("Failed to build model");
 
     let img = image::open(img_path).expect("Failed to open image");
-    let image_size = model.vision_params().image_size();
-    let img = img.resize_exact(image_size as _, image_size as _, image::imageops::FilterType::Triangle);
     let img = img.to_rgb8();
 
-    let img = clip::RGBImage::new(img.width(), img.height(), img.into_vec());
+    let img = clip::RGBImage::new(img.width(), img.height(), img.into_vec())
+        .expect("Failed to wrap image");
 
     let blob = model.preprocess_image(&img).expect("Failed to preprocess");
     let tokens = model.tokenize(text).expect("Failed to tokenize");
-    let v = model.encode_tokens(&tokens, false);
+    let v = model
+        .try_encode_tokens(&tokens, false)
+        .expect("Failed to encode tokens");
     // let v = model.encode_text(text, false);
-    let z = model.encode_image(&blob, false);
+    let z = model
+        .try_encode_image(&blob, false)
+        .expect("Failed to encode image");
 
-    println!("score: {:?}", score(&v, &z));
+    let score = v
+        .cosine_similarity(&z)
+        .expect("Failed to compare embeddings");
+    println!("score: {:?}", score);
 }
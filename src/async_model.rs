@@ -0,0 +1,234 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+use tokio::sync::{mpsc, oneshot};
+
+use super::{Embedding, Embeddings, Error, Image, Model, ModelBuilder};
+
+type Job<W> = Box<dyn FnOnce(&W) + Send>;
+
+/// Worker threads, each owning a `W`, taking jobs from one bounded queue.
+struct Workers<W> {
+    jobs: mpsc::Sender<Job<W>>,
+}
+
+impl<W: Send + 'static> Workers<W> {
+    /// Starts one thread per item of `states`, accepting up to `queue` pending jobs.
+    fn spawn(states: Vec<W>, queue: usize) -> Result<Self, Error> {
+        let (jobs, receiver) = mpsc::channel::<Job<W>>(queue.max(1));
+        let receiver = Arc::new(Mutex::new(receiver));
+        for (i, state) in states.into_iter().enumerate() {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("clip-worker-{i}"))
+                .spawn(move || loop {
+                    let job = receiver
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .blocking_recv();
+                    let Some(job) = job else { break };
+                    // A panicking request drops its reply channel, the caller sees it as stopped.
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| job(&state)));
+                })?;
+        }
+        Ok(Self { jobs })
+    }
+
+    /// Runs `f` on the first idle worker, unless the returned future is dropped first.
+    async fn run<F, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&W) -> Result<T, Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let job: Job<W> = Box::new(move |state| {
+            if !reply.is_closed() {
+                let _ = reply.send(f(state));
+            }
+        });
+        self.jobs
+            .send(job)
+            .await
+            .map_err(|_| Error::WorkerStopped)?;
+        result.await.map_err(|_| Error::WorkerStopped)?
+    }
+}
+
+/// An async front end to a set of models, each running on a dedicated worker thread.
+///
+/// Requests wait in a bounded queue, callers are suspended while it is full. Every future is
+/// cancellation safe: a request dropped while waiting for queue space is never queued, and one
+/// dropped while queued is skipped by the workers.
+pub struct AsyncModel {
+    workers: Workers<Model>,
+}
+
+impl AsyncModel {
+    /// Loads `workers` contexts with the settings of `builder`, accepting up to `queue` pending
+    /// requests.
+    pub fn new(builder: ModelBuilder, workers: usize, queue: usize) -> Result<Self, Error> {
+        if workers == 0 {
+            return Err(Error::EmptyPool);
+        }
+        let models = (0..workers)
+            .map(|_| builder.clone().build())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            workers: Workers::spawn(models, queue)?,
+        })
+    }
+
+    /// Runs `f` on the first idle worker.
+    pub async fn run<F, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Model) -> Result<T, Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.workers.run(f).await
+    }
+
+    pub async fn encode_text<T>(&self, text: T, normalize: bool) -> Result<Embedding, Error>
+    where
+        T: AsRef<str> + Send + 'static,
+    {
+        self.run(move |model| model.encode_text(text, normalize))
+            .await
+    }
+
+    pub async fn encode_texts<T>(&self, texts: Vec<T>, normalize: bool) -> Result<Embeddings, Error>
+    where
+        T: AsRef<str> + Send + 'static,
+    {
+        self.run(move |model| model.encode_texts(texts, normalize))
+            .await
+    }
+
+    /// Preprocesses `image` with the model's preprocessing and encodes it.
+    pub async fn encode_image<I>(&self, image: I, normalize: bool) -> Result<Embedding, Error>
+    where
+        I: Image + Send + 'static,
+    {
+        self.run(move |model| {
+            let blob = model.preprocess_image(image)?;
+            model.try_encode_image(&blob, normalize)
+        })
+        .await
+    }
+
+    /// Preprocesses `images` with the model's preprocessing and encodes them as one batch.
+    pub async fn encode_images<I>(
+        &self,
+        images: Vec<I>,
+        normalize: bool,
+    ) -> Result<Embeddings, Error>
+    where
+        I: Image + Send + 'static,
+    {
+        self.run(move |model| {
+            let blobs = model.preprocess_images(images)?;
+            model.try_encode_images(&blobs, normalize)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::{pin, Pin};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc as std_mpsc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    use super::*;
+
+    /// Polls `future` on the current thread until it completes, without a runtime.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct Unpark(thread::Thread);
+
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    /// Polls `future` once, which queues its job if there is room for it.
+    fn poll_once<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+        Pin::new(future).poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn runs_jobs_on_the_worker_state() {
+        let workers = Workers::spawn(vec![40, 40], 4).unwrap();
+        assert_eq!(block_on(workers.run(|n| Ok(n + 2))).unwrap(), 42);
+        assert!(matches!(
+            block_on(workers.run(|_| Err::<(), _>(Error::ImageEncode))),
+            Err(Error::ImageEncode)
+        ));
+    }
+
+    #[test]
+    fn dropped_requests_are_skipped() {
+        let workers = Workers::spawn(vec![()], 4).unwrap();
+        let (release, gate) = std_mpsc::channel::<()>();
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        // Keep the only worker busy until released.
+        let mut busy = Box::pin(workers.run(move |_| {
+            gate.recv().unwrap();
+            Ok(())
+        }));
+        assert!(poll_once(&mut busy).is_pending());
+
+        // Queued behind it, then cancelled.
+        let counter = ran.clone();
+        let mut dropped = Box::pin(workers.run(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }));
+        assert!(poll_once(&mut dropped).is_pending());
+        drop(dropped);
+
+        release.send(()).unwrap();
+        block_on(busy).unwrap();
+        // Jobs run in order on the single worker, so the dropped one was reached by now.
+        let counter = ran.clone();
+        block_on(workers.run(move |_| {
+            counter.fetch_add(10, Ordering::SeqCst);
+            Ok(())
+        }))
+        .unwrap();
+        assert_eq!(ran.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn stopped_workers_fail_requests() {
+        // Without workers the queue has no receiver.
+        let workers = Workers::<()>::spawn(Vec::new(), 4).unwrap();
+        assert!(matches!(
+            block_on(workers.run(|_| Ok(()))),
+            Err(Error::WorkerStopped)
+        ));
+
+        // A panicking job drops its reply, the worker keeps serving requests.
+        let workers = Workers::spawn(vec![()], 4).unwrap();
+        assert!(matches!(
+            block_on(workers.run(|_| -> Result<(), Error> { panic!("job failed") })),
+            Err(Error::WorkerStopped)
+        ));
+        assert!(block_on(workers.run(|_| Ok(()))).is_ok());
+    }
+}
@@ -0,0 +1,463 @@
+use ndarray::{Array1, Array2, ArrayView1, ArrayViewMut1, Axis};
+
+use super::params::ModelParams;
+use super::{Error, TextParams, VisionParams};
+
+/// Encoder an embedding was produced by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Modality {
+    Text,
+    Image,
+}
+
+fn l2_normalize(mut v: ArrayViewMut1<f32>) {
+    let norm = v.dot(&v).sqrt();
+    if norm > 0.0 {
+        v /= norm;
+    }
+}
+
+/// Fails with [`Error::EmbeddingModel`] unless both embeddings come from the same model.
+fn check_model(a: &ModelParams, b: &ModelParams) -> Result<(), Error> {
+    if a != b {
+        return Err(Error::EmbeddingModel);
+    }
+    Ok(())
+}
+
+/// A single embedding produced by the text or image encoder of a [`Model`](crate::Model).
+///
+/// The embedding records the hyperparameters of the model it was produced by, embeddings of
+/// different models are not compared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Embedding {
+    data: Array1<f32>,
+    normalized: bool,
+    modality: Modality,
+    model: ModelParams,
+}
+
+impl Embedding {
+    pub(crate) fn new(
+        data: Vec<f32>,
+        normalized: bool,
+        modality: Modality,
+        model: ModelParams,
+    ) -> Self {
+        Self {
+            data: Array1::from_vec(data),
+            normalized,
+            modality,
+            model,
+        }
+    }
+
+    pub fn modality(&self) -> Modality {
+        self.modality
+    }
+
+    /// Text encoder hyperparameters of the model the embedding was produced by.
+    pub fn text_params(&self) -> &TextParams {
+        &self.model.text
+    }
+
+    /// Vision encoder hyperparameters of the model the embedding was produced by.
+    pub fn vision_params(&self) -> &VisionParams {
+        &self.model.vision
+    }
+
+    pub fn dim(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Whether the embedding has unit L2 norm.
+    pub fn is_normalized(&self) -> bool {
+        self.normalized
+    }
+
+    pub fn as_slice(&self) -> &[f32] {
+        self.data.as_slice().unwrap()
+    }
+
+    pub fn view(&self) -> ArrayView1<'_, f32> {
+        self.data.view()
+    }
+
+    pub fn into_vec(self) -> Vec<f32> {
+        self.data.into_raw_vec()
+    }
+
+    pub fn norm(&self) -> f32 {
+        self.data.dot(&self.data).sqrt()
+    }
+
+    /// Scales the embedding to unit L2 norm in place.
+    pub fn normalize(&mut self) {
+        if !self.normalized {
+            l2_normalize(self.data.view_mut());
+            self.normalized = true;
+        }
+    }
+
+    /// Returns a copy of the embedding scaled to unit L2 norm.
+    pub fn normalized(mut self) -> Self {
+        self.normalize();
+        self
+    }
+
+    /// Fails with [`Error::EmbeddingModel`] if the embeddings come from different models.
+    pub fn dot(&self, other: &Embedding) -> Result<f32, Error> {
+        check_model(&self.model, &other.model)?;
+        Ok(self.data.dot(&other.data))
+    }
+
+    /// Fails with [`Error::EmbeddingModel`] if the embeddings come from different models.
+    pub fn cosine_similarity(&self, other: &Embedding) -> Result<f32, Error> {
+        let dot = self.dot(other)?;
+        Ok(match (self.normalized, other.normalized) {
+            (true, true) => dot,
+            _ => {
+                let norm = self.norm() * other.norm();
+                if norm > 0.0 {
+                    dot / norm
+                } else {
+                    0.0
+                }
+            }
+        })
+    }
+}
+
+impl AsRef<[f32]> for Embedding {
+    fn as_ref(&self) -> &[f32] {
+        self.as_slice()
+    }
+}
+
+impl From<Embedding> for Vec<f32> {
+    fn from(embedding: Embedding) -> Self {
+        embedding.into_vec()
+    }
+}
+
+/// A set of embeddings of the same modality and model stored as the rows of a contiguous
+/// `(len, dim)` array.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Embeddings {
+    data: Array2<f32>,
+    normalized: bool,
+    modality: Modality,
+    model: ModelParams,
+}
+
+impl Embeddings {
+    pub(crate) fn new(
+        data: Array2<f32>,
+        normalized: bool,
+        modality: Modality,
+        model: ModelParams,
+    ) -> Self {
+        Self {
+            data,
+            normalized,
+            modality,
+            model,
+        }
+    }
+
+    /// Stacks single embeddings into a set.
+    ///
+    /// The set is only marked normalized if every embedding is.
+    pub fn stack(embeddings: &[Embedding]) -> Result<Self, Error> {
+        let first = embeddings.first().ok_or(Error::EmptyEmbeddings)?;
+        let views = embeddings
+            .iter()
+            .map(|e| {
+                if e.dim() != first.dim() {
+                    return Err(Error::Dimension {
+                        expected: first.dim(),
+                        found: e.dim(),
+                    });
+                }
+                if e.modality != first.modality {
+                    return Err(Error::Modality);
+                }
+                check_model(&first.model, &e.model)?;
+                Ok(e.view())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            data: ndarray::stack(Axis(0), &views).unwrap(),
+            normalized: embeddings.iter().all(|e| e.normalized),
+            modality: first.modality,
+            model: first.model,
+        })
+    }
+
+    pub fn modality(&self) -> Modality {
+        self.modality
+    }
+
+    /// Text encoder hyperparameters of the model the embeddings were produced by.
+    pub fn text_params(&self) -> &TextParams {
+        &self.model.text
+    }
+
+    /// Vision encoder hyperparameters of the model the embeddings were produced by.
+    pub fn vision_params(&self) -> &VisionParams {
+        &self.model.vision
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.nrows()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.nrows() == 0
+    }
+
+    pub fn dim(&self) -> usize {
+        self.data.ncols()
+    }
+
+    /// Whether every row has unit L2 norm.
+    pub fn is_normalized(&self) -> bool {
+        self.normalized
+    }
+
+    pub fn as_array(&self) -> &Array2<f32> {
+        &self.data
+    }
+
+    pub fn into_array(self) -> Array2<f32> {
+        self.data
+    }
+
+    pub fn get(&self, index: usize) -> Option<Embedding> {
+        (index < self.len()).then(|| Embedding {
+            data: self.data.row(index).to_owned(),
+            normalized: self.normalized,
+            modality: self.modality,
+            model: self.model,
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Embedding> + '_ {
+        self.data.rows().into_iter().map(|row| Embedding {
+            data: row.to_owned(),
+            normalized: self.normalized,
+            modality: self.modality,
+            model: self.model,
+        })
+    }
+
+    /// Scales every row to unit L2 norm in place.
+    pub fn normalize(&mut self) {
+        if !self.normalized {
+            self.data.rows_mut().into_iter().for_each(l2_normalize);
+            self.normalized = true;
+        }
+    }
+
+    /// Returns a copy of the set with every row scaled to unit L2 norm.
+    pub fn normalized(mut self) -> Self {
+        self.normalize();
+        self
+    }
+
+    /// Dot products between every row of `self` and every row of `other`, as a
+    /// `(self.len(), other.len())` matrix.
+    ///
+    /// Fails with [`Error::EmbeddingModel`] if the sets come from different models.
+    pub fn dot(&self, other: &Embeddings) -> Result<Array2<f32>, Error> {
+        check_model(&self.model, &other.model)?;
+        Ok(self.data.dot(&other.data.t()))
+    }
+
+    /// Cosine similarities between every row of `self` and every row of `other`, as a
+    /// `(self.len(), other.len())` matrix.
+    ///
+    /// Fails with [`Error::EmbeddingModel`] if the sets come from different models.
+    pub fn cosine_similarity(&self, other: &Embeddings) -> Result<Array2<f32>, Error> {
+        match (self.normalized, other.normalized) {
+            (true, true) => self.dot(other),
+            (true, false) => self.dot(&other.clone().normalized()),
+            (false, true) => self.clone().normalized().dot(other),
+            (false, false) => self.clone().normalized().dot(&other.clone().normalized()),
+        }
+    }
+
+    /// Cosine similarities between every row of `self` and `other`.
+    ///
+    /// Fails with [`Error::EmbeddingModel`] if the embeddings come from different models.
+    pub fn cosine_similarity_to(&self, other: &Embedding) -> Result<Array1<f32>, Error> {
+        check_model(&self.model, &other.model)?;
+        let other = other.clone().normalized();
+        Ok(if self.normalized {
+            self.data.dot(&other.data)
+        } else {
+            self.clone().normalized().data.dot(&other.data)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embedding(data: &[f32], modality: Modality) -> Embedding {
+        Embedding::new(
+            data.to_vec(),
+            false,
+            modality,
+            ModelParams::test(data.len() as i32, 224),
+        )
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-6, "{a} != {b}");
+    }
+
+    #[test]
+    fn normalize_scales_to_unit_norm() {
+        let e = embedding(&[3.0, 4.0], Modality::Text);
+        assert!(!e.is_normalized());
+        assert_close(e.norm(), 5.0);
+
+        let e = e.normalized();
+        assert!(e.is_normalized());
+        assert_close(e.norm(), 1.0);
+        assert_close(e.as_slice()[0], 0.6);
+        assert_close(e.as_slice()[1], 0.8);
+    }
+
+    #[test]
+    fn normalize_leaves_zero_vector_unchanged() {
+        let zero = embedding(&[0.0, 0.0], Modality::Text).normalized();
+        assert_eq!(zero.as_slice(), [0.0, 0.0]);
+
+        let other = embedding(&[1.0, 0.0], Modality::Image);
+        assert_eq!(zero.cosine_similarity(&other).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn cosine_similarity_ignores_magnitude() {
+        let text = embedding(&[1.0, 0.0], Modality::Text);
+        let image = embedding(&[2.0, 2.0], Modality::Image);
+        let expected = std::f32::consts::FRAC_1_SQRT_2;
+
+        assert_close(text.dot(&image).unwrap(), 2.0);
+        assert_close(text.cosine_similarity(&image).unwrap(), expected);
+        assert_close(image.cosine_similarity(&text).unwrap(), expected);
+
+        let (text, image) = (text.normalized(), image.normalized());
+        assert_close(text.cosine_similarity(&image).unwrap(), expected);
+    }
+
+    #[test]
+    fn embeddings_of_different_models_are_not_compared() {
+        let a = embedding(&[1.0, 0.0], Modality::Text);
+        let b = Embedding::new(
+            vec![1.0, 0.0],
+            false,
+            Modality::Image,
+            ModelParams::test(2, 336),
+        );
+
+        assert!(matches!(a.dot(&b), Err(Error::EmbeddingModel)));
+        assert!(matches!(
+            a.cosine_similarity(&b),
+            Err(Error::EmbeddingModel)
+        ));
+
+        let set = Embeddings::stack(&[a]).unwrap();
+        let other = Embeddings::stack(std::slice::from_ref(&b)).unwrap();
+        assert!(matches!(set.dot(&other), Err(Error::EmbeddingModel)));
+        assert!(matches!(
+            set.cosine_similarity(&other),
+            Err(Error::EmbeddingModel)
+        ));
+        assert!(matches!(
+            set.cosine_similarity_to(&b),
+            Err(Error::EmbeddingModel)
+        ));
+    }
+
+    #[test]
+    fn stack_checks_embeddings_match() {
+        let a = embedding(&[1.0, 0.0], Modality::Text);
+
+        assert!(matches!(
+            Embeddings::stack(&[]),
+            Err(Error::EmptyEmbeddings)
+        ));
+        assert!(matches!(
+            Embeddings::stack(&[a.clone(), embedding(&[1.0, 0.0, 0.0], Modality::Text)]),
+            Err(Error::Dimension {
+                expected: 2,
+                found: 3
+            })
+        ));
+        assert!(matches!(
+            Embeddings::stack(&[a.clone(), embedding(&[1.0, 0.0], Modality::Image)]),
+            Err(Error::Modality)
+        ));
+        let other = Embedding::new(
+            vec![1.0, 0.0],
+            false,
+            Modality::Text,
+            ModelParams::test(2, 336),
+        );
+        assert!(matches!(
+            Embeddings::stack(&[a, other]),
+            Err(Error::EmbeddingModel)
+        ));
+    }
+
+    #[test]
+    fn stack_keeps_rows_in_order() {
+        let a = embedding(&[1.0, 0.0], Modality::Text).normalized();
+        let b = embedding(&[3.0, 4.0], Modality::Text);
+
+        let set = Embeddings::stack(&[a.clone(), b.clone()]).unwrap();
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.dim(), 2);
+        assert_eq!(set.modality(), Modality::Text);
+        assert!(!set.is_normalized());
+        assert_eq!(set.get(0).unwrap().as_slice(), a.as_slice());
+        assert_eq!(set.get(1).unwrap().as_slice(), b.as_slice());
+        assert!(set.get(2).is_none());
+
+        let set = Embeddings::stack(&[a, b.normalized()]).unwrap();
+        assert!(set.is_normalized());
+    }
+
+    #[test]
+    fn similarity_matrices_match_pairwise_similarities() {
+        let texts = [[1.0, 0.0, 0.0], [0.0, 2.0, 0.0], [1.0, 1.0, 1.0]]
+            .map(|t| embedding(&t, Modality::Text));
+        let images = [[3.0, 4.0, 0.0], [0.0, 0.0, -1.0]].map(|i| embedding(&i, Modality::Image));
+        let text_set = Embeddings::stack(&texts).unwrap();
+        let image_set = Embeddings::stack(&images).unwrap();
+
+        let dot = image_set.dot(&text_set).unwrap();
+        let cosine = image_set.cosine_similarity(&text_set).unwrap();
+        let normalized = image_set
+            .clone()
+            .normalized()
+            .cosine_similarity(&text_set.clone().normalized())
+            .unwrap();
+        assert_eq!(cosine.dim(), (2, 3));
+        for (i, image) in images.iter().enumerate() {
+            let to = text_set.cosine_similarity_to(image).unwrap();
+            for (t, text) in texts.iter().enumerate() {
+                let expected = image.cosine_similarity(text).unwrap();
+                assert_close(dot[(i, t)], image.dot(text).unwrap());
+                assert_close(cosine[(i, t)], expected);
+                assert_close(normalized[(i, t)], expected);
+                assert_close(to[t], expected);
+            }
+        }
+    }
+}
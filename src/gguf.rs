@@ -0,0 +1,849 @@
+//! Pure-Rust reader for the metadata of GGUF model files.
+//!
+//! Reading a header only parses the key-value metadata and the tensor list, it does not touch the
+//! weights, so a model file can be inspected without loading it into clip.cpp.
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use super::{Error, TextParams, VisionParams};
+
+const MAGIC: &[u8; 4] = b"GGUF";
+
+/// Key of the token list written by the clip.cpp conversion scripts.
+pub(crate) const KEY_TOKENS: &str = "tokenizer.ggml.tokens";
+pub(crate) const KEY_MERGES: &str = "tokenizer.ggml.merges";
+pub(crate) const KEY_N_POSITIONS: &str = "clip.text.context_length";
+
+const KEY_NAME: &str = "general.name";
+const KEY_ARCHITECTURE: &str = "general.architecture";
+const KEY_FILE_TYPE: &str = "general.file_type";
+const KEY_HAS_TEXT_ENCODER: &str = "clip.has_text_encoder";
+const KEY_HAS_VISION_ENCODER: &str = "clip.has_vision_encoder";
+const KEY_IMAGE_MEAN: &str = "clip.vision.image_mean";
+const KEY_IMAGE_STD: &str = "clip.vision.image_std";
+
+/// Hyperparameter keys of one encoder, `clip.{text,vision}.*`.
+struct EncoderKeys {
+    embedding_length: &'static str,
+    feed_forward_length: &'static str,
+    block_count: &'static str,
+    head_count: &'static str,
+    layer_norm_epsilon: &'static str,
+    projection_dim: &'static str,
+}
+
+macro_rules! encoder_keys {
+    ($encoder:literal) => {
+        EncoderKeys {
+            embedding_length: concat!("clip.", $encoder, ".embedding_length"),
+            feed_forward_length: concat!("clip.", $encoder, ".feed_forward_length"),
+            block_count: concat!("clip.", $encoder, ".block_count"),
+            head_count: concat!("clip.", $encoder, ".attention.head_count"),
+            layer_norm_epsilon: concat!("clip.", $encoder, ".attention.layer_norm_epsilon"),
+            projection_dim: concat!("clip.", $encoder, ".projection_dim"),
+        }
+    };
+}
+
+const TEXT_KEYS: EncoderKeys = encoder_keys!("text");
+const VISION_KEYS: EncoderKeys = encoder_keys!("vision");
+const KEY_IMAGE_SIZE: &str = "clip.vision.image_size";
+const KEY_PATCH_SIZE: &str = "clip.vision.patch_size";
+
+/// A metadata value stored in a GGUF header.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+    Bool(bool),
+    String(String),
+    Array(Vec<Value>),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Self::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// The value of any integer type, if it is not negative.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Self::U8(v) => Some(v.into()),
+            Self::U16(v) => Some(v.into()),
+            Self::U32(v) => Some(v.into()),
+            Self::U64(v) => Some(v),
+            Self::I8(v) => v.try_into().ok(),
+            Self::I16(v) => v.try_into().ok(),
+            Self::I32(v) => v.try_into().ok(),
+            Self::I64(v) => v.try_into().ok(),
+            _ => None,
+        }
+    }
+
+    /// The value of any float type.
+    pub fn as_f32(&self) -> Option<f32> {
+        match *self {
+            Self::F32(v) => Some(v),
+            Self::F64(v) => Some(v as f32),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Self::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// Element type of a tensor, the `ggml_type` values.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GgmlType {
+    F32,
+    F16,
+    Q4_0,
+    Q4_1,
+    Q5_0,
+    Q5_1,
+    Q8_0,
+    Q8_1,
+    Q2_K,
+    Q3_K,
+    Q4_K,
+    Q5_K,
+    Q6_K,
+    Q8_K,
+    /// A type this crate does not know about.
+    Other(u32),
+}
+
+impl From<u32> for GgmlType {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => Self::F32,
+            1 => Self::F16,
+            2 => Self::Q4_0,
+            3 => Self::Q4_1,
+            6 => Self::Q5_0,
+            7 => Self::Q5_1,
+            8 => Self::Q8_0,
+            9 => Self::Q8_1,
+            10 => Self::Q2_K,
+            11 => Self::Q3_K,
+            12 => Self::Q4_K,
+            13 => Self::Q5_K,
+            14 => Self::Q6_K,
+            15 => Self::Q8_K,
+            other => Self::Other(other),
+        }
+    }
+}
+
+/// Name, shape and type of a tensor stored in a GGUF file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TensorInfo {
+    name: String,
+    shape: Vec<u64>,
+    dtype: GgmlType,
+    offset: u64,
+}
+
+impl TensorInfo {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Dimensions in ggml order, innermost first.
+    pub fn shape(&self) -> &[u64] {
+        &self.shape
+    }
+
+    pub fn dtype(&self) -> GgmlType {
+        self.dtype
+    }
+
+    /// Offset of the tensor data from the start of the data section.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Number of elements, `None` if the shape overflows a `u64`.
+    pub fn n_elements(&self) -> Option<u64> {
+        self.shape
+            .iter()
+            .try_fold(1u64, |total, &dim| total.checked_mul(dim))
+    }
+}
+
+/// The metadata and tensor list of a GGUF file.
+#[derive(Debug, Clone)]
+pub struct Header {
+    version: u32,
+    metadata: Vec<(String, Value)>,
+    tensors: Vec<TensorInfo>,
+}
+
+impl Header {
+    pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Reads the header from the start of a GGUF stream, leaving the reader at the tensor data.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::Gguf("bad magic"));
+        }
+        let version = read_u32(&mut reader)?;
+        if !(1..=3).contains(&version) {
+            return Err(Error::Gguf("unsupported version"));
+        }
+        let mut reader = Reader { reader, version };
+
+        let tensor_count = reader.read_len()?;
+        let kv_count = reader.read_len()?;
+        let metadata = (0..kv_count)
+            .map(|_| {
+                let key = reader.read_string()?;
+                let kind = read_u32(&mut reader.reader)?;
+                Ok((key, reader.read_value(kind)?))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let tensors = (0..tensor_count)
+            .map(|_| reader.read_tensor_info())
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(Self {
+            version,
+            metadata,
+            tensors,
+        })
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Key-value pairs in file order.
+    pub fn metadata(&self) -> &[(String, Value)] {
+        &self.metadata
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.metadata
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn tensors(&self) -> &[TensorInfo] {
+        &self.tensors
+    }
+
+    /// Fails with [`Error::GgufKey`] if `key` is missing or not a non-negative integer.
+    fn u32(&self, key: &'static str) -> Result<i32, Error> {
+        self.get(key)
+            .and_then(Value::as_u64)
+            .and_then(|v| i32::try_from(v).ok())
+            .ok_or(Error::GgufKey(key))
+    }
+
+    /// Fails with [`Error::GgufKey`] if `key` is missing or not a float.
+    fn f32(&self, key: &'static str) -> Result<f32, Error> {
+        self.get(key)
+            .and_then(Value::as_f32)
+            .ok_or(Error::GgufKey(key))
+    }
+
+    /// Reads a `[f32; 3]` array, `None` if `key` is missing.
+    fn rgb(&self, key: &'static str) -> Result<Option<[f32; 3]>, Error> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+        let rgb = value
+            .as_array()
+            .filter(|a| a.len() == 3)
+            .and_then(|a| Some([a[0].as_f32()?, a[1].as_f32()?, a[2].as_f32()?]))
+            .ok_or(Error::GgufKey(key))?;
+        Ok(Some(rgb))
+    }
+
+    /// Whether the file declares an encoder, falling back to the presence of its hyperparameters.
+    fn has_encoder(&self, key: &'static str, keys: &EncoderKeys) -> Result<bool, Error> {
+        match self.get(key) {
+            Some(value) => value.as_bool().ok_or(Error::GgufKey(key)),
+            None => Ok(self.get(keys.embedding_length).is_some()),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Header {
+    /// A version 3 header holding `metadata` and no tensors.
+    pub(crate) fn test(metadata: Vec<(String, Value)>) -> Self {
+        Self {
+            version: 3,
+            metadata,
+            tensors: Vec::new(),
+        }
+    }
+}
+
+/// A typed summary of a clip.cpp model file, read without loading the weights.
+///
+/// The hyperparameters are extracted the way clip.cpp does, so they compare equal to the
+/// [`Model::text_params`](crate::Model::text_params) and
+/// [`Model::vision_params`](crate::Model::vision_params) of the loaded model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelInfo {
+    name: Option<String>,
+    architecture: Option<String>,
+    file_type: Option<GgmlType>,
+    text_params: Option<TextParams>,
+    vision_params: Option<VisionParams>,
+    image_mean: Option<[f32; 3]>,
+    image_std: Option<[f32; 3]>,
+    vocab_size: Option<usize>,
+    tensors: Vec<TensorInfo>,
+}
+
+impl ModelInfo {
+    pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_header(Header::read_file(path)?)
+    }
+
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, Error> {
+        Self::from_header(Header::read_from(reader)?)
+    }
+
+    pub fn from_header(header: Header) -> Result<Self, Error> {
+        let string = |key| header.get(key).and_then(Value::as_str).map(str::to_owned);
+        let vocab_size = header
+            .get(KEY_TOKENS)
+            .and_then(Value::as_array)
+            .map(<[_]>::len);
+
+        let text_params = if header.has_encoder(KEY_HAS_TEXT_ENCODER, &TEXT_KEYS)? {
+            let keys = &TEXT_KEYS;
+            let params = clip_cpp_sys::clip_text_hparams {
+                n_vocab: vocab_size.ok_or(Error::GgufKey(KEY_TOKENS))? as i32,
+                num_positions: header.u32(KEY_N_POSITIONS)?,
+                hidden_size: header.u32(keys.embedding_length)?,
+                n_intermediate: header.u32(keys.feed_forward_length)?,
+                projection_dim: header.u32(keys.projection_dim)?,
+                n_head: header.u32(keys.head_count)?,
+                n_layer: header.u32(keys.block_count)?,
+                eps: header.f32(keys.layer_norm_epsilon)?,
+            };
+            Some(params.into())
+        } else {
+            None
+        };
+        let vision_params = if header.has_encoder(KEY_HAS_VISION_ENCODER, &VISION_KEYS)? {
+            let keys = &VISION_KEYS;
+            let params = clip_cpp_sys::clip_vision_hparams {
+                image_size: header.u32(KEY_IMAGE_SIZE)?,
+                patch_size: header.u32(KEY_PATCH_SIZE)?,
+                hidden_size: header.u32(keys.embedding_length)?,
+                n_intermediate: header.u32(keys.feed_forward_length)?,
+                projection_dim: header.u32(keys.projection_dim)?,
+                n_head: header.u32(keys.head_count)?,
+                n_layer: header.u32(keys.block_count)?,
+                eps: header.f32(keys.layer_norm_epsilon)?,
+            };
+            Some(params.into())
+        } else {
+            None
+        };
+
+        Ok(Self {
+            name: string(KEY_NAME),
+            architecture: string(KEY_ARCHITECTURE),
+            file_type: header
+                .get(KEY_FILE_TYPE)
+                .and_then(Value::as_u64)
+                .and_then(|v| u32::try_from(v).ok())
+                .map(GgmlType::from),
+            text_params,
+            vision_params,
+            image_mean: header.rgb(KEY_IMAGE_MEAN)?,
+            image_std: header.rgb(KEY_IMAGE_STD)?,
+            vocab_size,
+            tensors: header.tensors,
+        })
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// `general.architecture`, `clip` for files written by the clip.cpp conversion scripts.
+    pub fn architecture(&self) -> Option<&str> {
+        self.architecture.as_deref()
+    }
+
+    /// Predominant tensor type, e.g. [`GgmlType::Q4_1`] for a model quantized to Q4_1.
+    pub fn file_type(&self) -> Option<GgmlType> {
+        self.file_type
+    }
+
+    /// Hyperparameters of the text encoder, if the file has one.
+    pub fn text_params(&self) -> Option<&TextParams> {
+        self.text_params.as_ref()
+    }
+
+    /// Hyperparameters of the vision encoder, if the file has one.
+    pub fn vision_params(&self) -> Option<&VisionParams> {
+        self.vision_params.as_ref()
+    }
+
+    pub fn image_mean(&self) -> Option<[f32; 3]> {
+        self.image_mean
+    }
+
+    pub fn image_std(&self) -> Option<[f32; 3]> {
+        self.image_std
+    }
+
+    /// Number of tokens in the tokenizer vocabulary.
+    pub fn vocab_size(&self) -> Option<usize> {
+        self.vocab_size
+    }
+
+    pub fn tensors(&self) -> &[TensorInfo] {
+        &self.tensors
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, Error> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+/// Reads GGUF primitives, accounting for the 32-bit lengths used by version 1.
+struct Reader<R> {
+    reader: R,
+    version: u32,
+}
+
+impl<R: Read> Reader<R> {
+    fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut buf = [0u8; N];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_len(&mut self) -> Result<u64, Error> {
+        if self.version == 1 {
+            Ok(u32::from_le_bytes(self.read_bytes()?) as u64)
+        } else {
+            Ok(u64::from_le_bytes(self.read_bytes()?))
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String, Error> {
+        let len = self.read_len()?;
+        let mut buf = Vec::new();
+        (&mut self.reader).take(len).read_to_end(&mut buf)?;
+        if buf.len() as u64 != len {
+            return Err(Error::Gguf("truncated string"));
+        }
+        String::from_utf8(buf).map_err(|_| Error::Gguf("string is not utf-8"))
+    }
+
+    fn read_value(&mut self, kind: u32) -> Result<Value, Error> {
+        Ok(match kind {
+            0 => Value::U8(u8::from_le_bytes(self.read_bytes()?)),
+            1 => Value::I8(i8::from_le_bytes(self.read_bytes()?)),
+            2 => Value::U16(u16::from_le_bytes(self.read_bytes()?)),
+            3 => Value::I16(i16::from_le_bytes(self.read_bytes()?)),
+            4 => Value::U32(u32::from_le_bytes(self.read_bytes()?)),
+            5 => Value::I32(i32::from_le_bytes(self.read_bytes()?)),
+            6 => Value::F32(f32::from_le_bytes(self.read_bytes()?)),
+            7 => Value::Bool(self.read_bytes::<1>()?[0] != 0),
+            8 => Value::String(self.read_string()?),
+            9 => {
+                let kind = read_u32(&mut self.reader)?;
+                // ggml doesn't support them either, and reading them recursively would let a
+                // small file exhaust the stack.
+                if kind == 9 {
+                    return Err(Error::Gguf("nested arrays are not supported"));
+                }
+                let len = self.read_len()?;
+                let values = (0..len)
+                    .map(|_| self.read_value(kind))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Value::Array(values)
+            }
+            10 => Value::U64(u64::from_le_bytes(self.read_bytes()?)),
+            11 => Value::I64(i64::from_le_bytes(self.read_bytes()?)),
+            12 => Value::F64(f64::from_le_bytes(self.read_bytes()?)),
+            _ => return Err(Error::Gguf("unknown value type")),
+        })
+    }
+
+    fn read_tensor_info(&mut self) -> Result<TensorInfo, Error> {
+        let name = self.read_string()?;
+        let n_dims = read_u32(&mut self.reader)?;
+        if n_dims > 4 {
+            return Err(Error::Gguf("tensor has more than 4 dimensions"));
+        }
+        let shape = (0..n_dims)
+            .map(|_| self.read_len())
+            .collect::<Result<Vec<_>, _>>()?;
+        let dtype = read_u32(&mut self.reader)?.into();
+        let offset = u64::from_le_bytes(self.read_bytes()?);
+        Ok(TensorInfo {
+            name,
+            shape,
+            dtype,
+            offset,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::ModelParams;
+
+    /// Writes GGUF headers, with the 32-bit lengths of version 1 where asked to.
+    struct Writer {
+        version: u32,
+        /// Key left out of the header, to test missing keys.
+        skip: &'static str,
+        kv_count: u64,
+        kvs: Vec<u8>,
+        tensor_count: u64,
+        tensors: Vec<u8>,
+    }
+
+    impl Writer {
+        fn new(version: u32, skip: &'static str) -> Self {
+            Self {
+                version,
+                skip,
+                kv_count: 0,
+                kvs: Vec::new(),
+                tensor_count: 0,
+                tensors: Vec::new(),
+            }
+        }
+
+        fn len(&self, len: u64) -> Vec<u8> {
+            match self.version {
+                1 => (len as u32).to_le_bytes().to_vec(),
+                _ => len.to_le_bytes().to_vec(),
+            }
+        }
+
+        fn string(&self, s: &str) -> Vec<u8> {
+            [self.len(s.len() as u64), s.as_bytes().to_vec()].concat()
+        }
+
+        /// Adds `key` with the encoded bytes of a value of type `kind`.
+        fn raw(mut self, key: &str, kind: u32, value: &[u8]) -> Self {
+            if key == self.skip {
+                return self;
+            }
+            let key = self.string(key);
+            self.kvs.extend(key);
+            self.kvs.extend(kind.to_le_bytes());
+            self.kvs.extend(value);
+            self.kv_count += 1;
+            self
+        }
+
+        fn u32(self, key: &str, value: u32) -> Self {
+            self.raw(key, 4, &value.to_le_bytes())
+        }
+
+        fn f32(self, key: &str, value: f32) -> Self {
+            self.raw(key, 6, &value.to_le_bytes())
+        }
+
+        fn bool(self, key: &str, value: bool) -> Self {
+            self.raw(key, 7, &[value as u8])
+        }
+
+        fn str(self, key: &str, value: &str) -> Self {
+            let value = self.string(value);
+            self.raw(key, 8, &value)
+        }
+
+        fn f32s(self, key: &str, values: &[f32]) -> Self {
+            let mut value = [6u32.to_le_bytes().to_vec(), self.len(values.len() as u64)].concat();
+            values.iter().for_each(|v| value.extend(v.to_le_bytes()));
+            self.raw(key, 9, &value)
+        }
+
+        fn strs(self, key: &str, values: &[&str]) -> Self {
+            let mut value = [8u32.to_le_bytes().to_vec(), self.len(values.len() as u64)].concat();
+            values.iter().for_each(|v| value.extend(self.string(v)));
+            self.raw(key, 9, &value)
+        }
+
+        fn tensor(mut self, name: &str, shape: &[u64], dtype: u32, offset: u64) -> Self {
+            let name = self.string(name);
+            self.tensors.extend(name);
+            self.tensors.extend((shape.len() as u32).to_le_bytes());
+            for &dim in shape {
+                let dim = self.len(dim);
+                self.tensors.extend(dim);
+            }
+            self.tensors.extend(dtype.to_le_bytes());
+            self.tensors.extend(offset.to_le_bytes());
+            self.tensor_count += 1;
+            self
+        }
+
+        fn finish(self) -> Vec<u8> {
+            [
+                MAGIC.to_vec(),
+                self.version.to_le_bytes().to_vec(),
+                self.len(self.tensor_count),
+                self.len(self.kv_count),
+                self.kvs,
+                self.tensors,
+            ]
+            .concat()
+        }
+    }
+
+    const MEAN: [f32; 3] = [0.48145466, 0.4578275, 0.40821073];
+    const STD: [f32; 3] = [0.26862954, 0.2613026, 0.2757771];
+
+    /// A ViT-B/32 header as written by the clip.cpp conversion script for a Q4_1 model, with a
+    /// three token vocabulary and without `skip`.
+    fn vit_b_32(version: u32, skip: &'static str) -> Vec<u8> {
+        Writer::new(version, skip)
+            .str(KEY_NAME, "openai/clip-vit-base-patch32")
+            .str(KEY_ARCHITECTURE, "clip")
+            .u32(KEY_FILE_TYPE, 3)
+            .bool(KEY_HAS_TEXT_ENCODER, true)
+            .bool(KEY_HAS_VISION_ENCODER, true)
+            .strs(KEY_TOKENS, &["<|startoftext|>", "<|endoftext|>", "a</w>"])
+            .u32(KEY_N_POSITIONS, 77)
+            .u32(TEXT_KEYS.embedding_length, 512)
+            .u32(TEXT_KEYS.feed_forward_length, 2048)
+            .u32(TEXT_KEYS.block_count, 12)
+            .u32(TEXT_KEYS.head_count, 8)
+            .f32(TEXT_KEYS.layer_norm_epsilon, 1e-5)
+            .u32(TEXT_KEYS.projection_dim, 512)
+            .u32(KEY_IMAGE_SIZE, 224)
+            .u32(KEY_PATCH_SIZE, 32)
+            .u32(VISION_KEYS.embedding_length, 768)
+            .u32(VISION_KEYS.feed_forward_length, 3072)
+            .u32(VISION_KEYS.block_count, 12)
+            .u32(VISION_KEYS.head_count, 12)
+            .f32(VISION_KEYS.layer_norm_epsilon, 1e-5)
+            .u32(VISION_KEYS.projection_dim, 512)
+            .f32s(KEY_IMAGE_MEAN, &MEAN)
+            .f32s(KEY_IMAGE_STD, &STD)
+            .tensor("v.patch_embd.weight", &[32, 32, 3, 768], 1, 0)
+            .tensor("t.token_embd.weight", &[512, 3], 3, 4718592)
+            .finish()
+    }
+
+    fn read(bytes: &[u8]) -> Result<ModelInfo, Error> {
+        ModelInfo::read_from(bytes)
+    }
+
+    #[test]
+    fn reads_hparams_of_v1_and_v3_headers() {
+        let text = TextParams::from(clip_cpp_sys::clip_text_hparams {
+            n_vocab: 3,
+            num_positions: 77,
+            hidden_size: 512,
+            n_intermediate: 2048,
+            projection_dim: 512,
+            n_head: 8,
+            n_layer: 12,
+            eps: 1e-5,
+        });
+        let vision = ModelParams::test(512, 224).vision;
+
+        for version in [1, 3] {
+            let header = Header::read_from(vit_b_32(version, "").as_slice()).unwrap();
+            assert_eq!(header.version(), version);
+            assert_eq!(header.metadata().len(), 23);
+
+            let info = ModelInfo::from_header(header).unwrap();
+            assert_eq!(info.name(), Some("openai/clip-vit-base-patch32"));
+            assert_eq!(info.architecture(), Some("clip"));
+            assert_eq!(info.file_type(), Some(GgmlType::Q4_1));
+            assert_eq!(info.text_params(), Some(&text));
+            assert_eq!(info.vision_params(), Some(&vision));
+            assert_eq!(info.image_mean(), Some(MEAN));
+            assert_eq!(info.image_std(), Some(STD));
+            assert_eq!(info.vocab_size(), Some(3));
+
+            let tensors = info.tensors();
+            assert_eq!(tensors.len(), 2);
+            assert_eq!(tensors[0].name(), "v.patch_embd.weight");
+            assert_eq!(tensors[0].shape(), [32, 32, 3, 768]);
+            assert_eq!(tensors[0].dtype(), GgmlType::F16);
+            assert_eq!(tensors[0].n_elements(), Some(32 * 32 * 3 * 768));
+            assert_eq!(tensors[1].dtype(), GgmlType::Q4_1);
+            assert_eq!(tensors[1].offset(), 4718592);
+        }
+    }
+
+    #[test]
+    fn oversized_shapes_have_no_element_count() {
+        let header = Writer::new(3, "")
+            .tensor("huge", &[1 << 32, 1 << 32], 0, 0)
+            .tensor("max", &[u64::MAX, 1], 0, 0)
+            .tensor("empty", &[u64::MAX, 0], 0, 0)
+            .finish();
+        let info = read(&header).unwrap();
+        let counts = info.tensors().iter().map(TensorInfo::n_elements);
+        assert_eq!(counts.collect::<Vec<_>>(), [None, Some(u64::MAX), Some(0)]);
+    }
+
+    #[test]
+    fn maps_file_types() {
+        for (value, expected) in [
+            (0, GgmlType::F32),
+            (1, GgmlType::F16),
+            (2, GgmlType::Q4_0),
+            (8, GgmlType::Q8_0),
+            (15, GgmlType::Q8_K),
+            (4, GgmlType::Other(4)),
+            (99, GgmlType::Other(99)),
+        ] {
+            let header = Writer::new(3, "").u32(KEY_FILE_TYPE, value).finish();
+            assert_eq!(read(&header).unwrap().file_type(), Some(expected));
+        }
+        assert_eq!(read(&vit_b_32(3, KEY_FILE_TYPE)).unwrap().file_type(), None);
+    }
+
+    #[test]
+    fn missing_hparams_are_errors() {
+        for key in [
+            KEY_TOKENS,
+            KEY_N_POSITIONS,
+            TEXT_KEYS.head_count,
+            TEXT_KEYS.layer_norm_epsilon,
+            KEY_IMAGE_SIZE,
+            KEY_PATCH_SIZE,
+            VISION_KEYS.projection_dim,
+        ] {
+            assert!(
+                matches!(read(&vit_b_32(3, key)), Err(Error::GgufKey(k)) if k == key),
+                "{key}"
+            );
+        }
+
+        // Optional keys.
+        for key in [KEY_NAME, KEY_ARCHITECTURE, KEY_IMAGE_MEAN, KEY_IMAGE_STD] {
+            assert!(read(&vit_b_32(3, key)).is_ok(), "{key}");
+        }
+
+        // Without the flag, an encoder is present if its embedding length is.
+        let info = read(&vit_b_32(3, KEY_HAS_TEXT_ENCODER)).unwrap();
+        assert!(info.text_params().is_some());
+        let header = Writer::new(3, "")
+            .bool(KEY_HAS_TEXT_ENCODER, false)
+            .finish();
+        let info = read(&header).unwrap();
+        assert_eq!(info.text_params(), None);
+        assert_eq!(info.vision_params(), None);
+    }
+
+    #[test]
+    fn mistyped_values_are_errors() {
+        let header = Writer::new(3, "")
+            .bool(KEY_HAS_VISION_ENCODER, false)
+            .str(KEY_HAS_TEXT_ENCODER, "yes")
+            .finish();
+        assert!(matches!(
+            read(&header),
+            Err(Error::GgufKey(KEY_HAS_TEXT_ENCODER))
+        ));
+
+        let header = Writer::new(3, "")
+            .bool(KEY_HAS_TEXT_ENCODER, false)
+            .bool(KEY_HAS_VISION_ENCODER, false)
+            .f32s(KEY_IMAGE_MEAN, &MEAN[..2])
+            .finish();
+        assert!(matches!(read(&header), Err(Error::GgufKey(KEY_IMAGE_MEAN))));
+    }
+
+    #[test]
+    fn truncated_headers_are_errors() {
+        for version in [1, 3] {
+            let header = vit_b_32(version, "");
+            for len in 0..header.len() {
+                assert!(
+                    matches!(
+                        Header::read_from(&header[..len]),
+                        Err(Error::Io(_) | Error::Gguf("truncated string"))
+                    ),
+                    "v{version}, {len} bytes"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_magic_and_versions() {
+        let mut header = vit_b_32(3, "");
+        header[0] = b'g';
+        assert!(matches!(read(&header), Err(Error::Gguf("bad magic"))));
+
+        for version in [0, 4] {
+            let header = Writer::new(version, "").finish();
+            assert!(matches!(
+                read(&header),
+                Err(Error::Gguf("unsupported version"))
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_nested_arrays() {
+        let header = Writer::new(3, "")
+            .raw("nested", 9, &9u32.to_le_bytes())
+            .finish();
+        assert!(matches!(
+            read(&header),
+            Err(Error::Gguf("nested arrays are not supported"))
+        ));
+
+        // Deep nesting fails at the first level rather than overflowing the stack.
+        let mut deep = Writer::new(3, "").raw("deep", 9, &[]).finish();
+        for _ in 0..200_000 {
+            deep.extend(9u32.to_le_bytes());
+            deep.extend(1u64.to_le_bytes());
+        }
+        assert!(matches!(
+            read(&deep),
+            Err(Error::Gguf("nested arrays are not supported"))
+        ));
+    }
+}
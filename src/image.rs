@@ -1,8 +1,160 @@
+use super::Error;
+
+/// Layout of the pixels returned by [`Image::data`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PixelFormat {
+    #[default]
+    Rgb8,
+    Rgba8,
+    Bgr8,
+    Bgra8,
+    Gray8,
+    GrayA8,
+    /// One native-endian `u16` sample per pixel.
+    Gray16,
+    /// Two native-endian `u16` samples per pixel.
+    GrayA16,
+    /// Three native-endian `u16` samples per pixel.
+    Rgb16,
+    /// Four native-endian `u16` samples per pixel.
+    Rgba16,
+    /// Three native-endian `f32` samples in `0.0..=1.0` per pixel.
+    Rgb32F,
+    /// Four native-endian `f32` samples in `0.0..=1.0` per pixel.
+    Rgba32F,
+    /// BT.601 limited range YUV 4:2:0: a luma plane followed by an interleaved UV plane with the
+    /// same stride.
+    Nv12,
+    /// BT.601 limited range YUV 4:2:0: a luma plane followed by U and V planes, each with half the
+    /// luma stride.
+    I420,
+}
+
+impl PixelFormat {
+    /// Bytes used by a single pixel, or by its luma sample for the YUV formats.
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Gray8 | Self::Nv12 | Self::I420 => 1,
+            Self::GrayA8 | Self::Gray16 => 2,
+            Self::Rgb8 | Self::Bgr8 => 3,
+            Self::Rgba8 | Self::Bgra8 | Self::GrayA16 => 4,
+            Self::Rgb16 => 6,
+            Self::Rgba16 => 8,
+            Self::Rgb32F => 12,
+            Self::Rgba32F => 16,
+        }
+    }
+
+    /// Smallest row stride able to hold `width` pixels. NV12 rows are padded to an even length so
+    /// the interleaved UV plane fits the same stride.
+    pub(crate) fn min_stride(self, width: usize) -> usize {
+        match self {
+            Self::Nv12 => width.next_multiple_of(2),
+            _ => width * self.bytes_per_pixel(),
+        }
+    }
+
+    /// Number of bytes in a tightly packed image with the given dimensions.
+    pub(crate) fn packed_len(self, width: usize, height: usize) -> usize {
+        let stride = self.min_stride(width);
+        match self {
+            Self::Nv12 | Self::I420 => self.min_len(width, height, stride),
+            _ => stride * height,
+        }
+    }
+
+    /// Number of bytes needed to hold an image with the given dimensions and row stride.
+    pub(crate) fn min_len(self, width: usize, height: usize, stride: usize) -> usize {
+        let chroma_rows = height.div_ceil(2);
+        match self {
+            Self::Nv12 => stride * (height + chroma_rows),
+            Self::I420 => stride * height + 2 * stride.div_ceil(2) * chroma_rows,
+            _ if height == 0 => 0,
+            _ => stride * (height - 1) + width * self.bytes_per_pixel(),
+        }
+    }
+}
+
 pub trait Image {
     fn width(&self) -> u32;
     fn height(&self) -> u32;
     fn size(&self) -> usize;
     fn data(&self) -> &[u8];
+
+    /// Layout of [`data`](Self::data), tightly packed RGB by default.
+    fn format(&self) -> PixelFormat {
+        PixelFormat::Rgb8
+    }
+
+    /// Distance in bytes between the starts of consecutive rows, tightly packed by default.
+    fn stride(&self) -> usize {
+        self.format().min_stride(self.width() as usize)
+    }
+}
+
+impl<I: Image + ?Sized> Image for &I {
+    fn width(&self) -> u32 {
+        (**self).width()
+    }
+
+    fn height(&self) -> u32 {
+        (**self).height()
+    }
+
+    fn size(&self) -> usize {
+        (**self).size()
+    }
+
+    fn data(&self) -> &[u8] {
+        (**self).data()
+    }
+
+    fn format(&self) -> PixelFormat {
+        (**self).format()
+    }
+
+    fn stride(&self) -> usize {
+        (**self).stride()
+    }
+}
+
+/// Fails with [`Error::DataLength`] unless `len` is the size of a tightly packed `format` image.
+fn check_len(format: PixelFormat, width: u32, height: u32, len: usize) -> Result<(), Error> {
+    let expected = format.packed_len(width as usize, height as usize);
+    if len != expected {
+        return Err(Error::DataLength {
+            width,
+            height,
+            expected,
+            found: len,
+        });
+    }
+    Ok(())
+}
+
+/// Fails with [`Error::Stride`] if rows of `stride` bytes can't hold `width` pixels, or with
+/// [`Error::DataLength`] if `len` is too short for a `format` image with that stride.
+fn check_strided_len(
+    format: PixelFormat,
+    width: u32,
+    height: u32,
+    stride: usize,
+    len: usize,
+) -> Result<(), Error> {
+    let min = format.min_stride(width as usize);
+    if stride < min {
+        return Err(Error::Stride { width, stride, min });
+    }
+    let expected = format.min_len(width as usize, height as usize, stride);
+    if len < expected {
+        return Err(Error::DataLength {
+            width,
+            height,
+            expected,
+            found: len,
+        });
+    }
+    Ok(())
 }
 
 pub struct RGBImage {
@@ -12,16 +164,136 @@ pub struct RGBImage {
 }
 
 impl RGBImage {
-    pub fn new(width: u32, height: u32, data: Vec<u8>) -> Self {
-        Self {
+    /// `data` holds tightly packed RGB pixels, row by row.
+    pub fn new(width: u32, height: u32, data: Vec<u8>) -> Result<Self, Error> {
+        check_len(PixelFormat::Rgb8, width, height, data.len())?;
+        Ok(Self {
             width,
             height,
             data,
-        }
+        })
+    }
+}
+
+impl Image for RGBImage {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+}
+
+/// RGB pixels borrowed from a caller-owned buffer.
+#[derive(Debug, Clone, Copy)]
+pub struct RGBImageView<'a> {
+    width: u32,
+    height: u32,
+    stride: usize,
+    data: &'a [u8],
+}
+
+impl<'a> RGBImageView<'a> {
+    /// `data` holds tightly packed RGB pixels, row by row.
+    pub fn new(width: u32, height: u32, data: &'a [u8]) -> Result<Self, Error> {
+        check_len(PixelFormat::Rgb8, width, height, data.len())?;
+        Ok(Self {
+            width,
+            height,
+            stride: width as usize * 3,
+            data,
+        })
+    }
+
+    /// `data` holds rows of RGB pixels starting `stride` bytes apart, as in padded frame buffers.
+    /// The last row doesn't need to be padded.
+    pub fn with_stride(
+        width: u32,
+        height: u32,
+        stride: usize,
+        data: &'a [u8],
+    ) -> Result<Self, Error> {
+        check_strided_len(PixelFormat::Rgb8, width, height, stride, data.len())?;
+        Ok(Self {
+            width,
+            height,
+            stride,
+            data,
+        })
     }
 }
 
-impl Image for &RGBImage {
+impl Image for RGBImageView<'_> {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn data(&self) -> &[u8] {
+        self.data
+    }
+
+    fn stride(&self) -> usize {
+        self.stride
+    }
+}
+
+/// A YUV 4:2:0 frame with an interleaved UV plane, as produced by most camera pipelines.
+pub struct NV12Image {
+    width: u32,
+    height: u32,
+    stride: usize,
+    data: Vec<u8>,
+}
+
+impl NV12Image {
+    /// `data` holds the luma plane followed by the interleaved UV plane, with rows of `width`
+    /// bytes rounded up to an even length.
+    pub fn new(width: u32, height: u32, data: Vec<u8>) -> Result<Self, Error> {
+        check_len(PixelFormat::Nv12, width, height, data.len())?;
+        Ok(Self {
+            width,
+            height,
+            stride: PixelFormat::Nv12.min_stride(width as usize),
+            data,
+        })
+    }
+
+    /// `data` holds the luma plane followed by the interleaved UV plane, both with rows starting
+    /// `stride` bytes apart.
+    pub fn with_stride(
+        width: u32,
+        height: u32,
+        stride: usize,
+        data: Vec<u8>,
+    ) -> Result<Self, Error> {
+        check_strided_len(PixelFormat::Nv12, width, height, stride, data.len())?;
+        Ok(Self {
+            width,
+            height,
+            stride,
+            data,
+        })
+    }
+}
+
+impl Image for NV12Image {
     fn width(&self) -> u32 {
         self.width
     }
@@ -37,4 +309,132 @@ impl Image for &RGBImage {
     fn data(&self) -> &[u8] {
         self.data.as_ref()
     }
+
+    fn format(&self) -> PixelFormat {
+        PixelFormat::Nv12
+    }
+
+    fn stride(&self) -> usize {
+        self.stride
+    }
+}
+
+/// A planar YUV 4:2:0 frame.
+pub struct I420Image {
+    width: u32,
+    height: u32,
+    stride: usize,
+    data: Vec<u8>,
+}
+
+impl I420Image {
+    /// `data` holds the `width x height` luma plane followed by the U and V planes.
+    pub fn new(width: u32, height: u32, data: Vec<u8>) -> Result<Self, Error> {
+        check_len(PixelFormat::I420, width, height, data.len())?;
+        Ok(Self {
+            width,
+            height,
+            stride: width as usize,
+            data,
+        })
+    }
+
+    /// `data` holds the luma plane with rows starting `stride` bytes apart, followed by the U and V
+    /// planes with half that stride, rounded up.
+    pub fn with_stride(
+        width: u32,
+        height: u32,
+        stride: usize,
+        data: Vec<u8>,
+    ) -> Result<Self, Error> {
+        check_strided_len(PixelFormat::I420, width, height, stride, data.len())?;
+        Ok(Self {
+            width,
+            height,
+            stride,
+            data,
+        })
+    }
+}
+
+impl Image for I420Image {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    fn format(&self) -> PixelFormat {
+        PixelFormat::I420
+    }
+
+    fn stride(&self) -> usize {
+        self.stride
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_with_stride_checks_layout() {
+        let data = [0; 2 * 10 + 6];
+        let view = RGBImageView::with_stride(2, 3, 10, &data).unwrap();
+        assert_eq!(view.stride(), 10);
+        assert_eq!(RGBImageView::new(2, 3, &data[..18]).unwrap().stride(), 6);
+
+        assert!(matches!(
+            RGBImageView::with_stride(2, 3, 5, &data),
+            Err(Error::Stride {
+                width: 2,
+                stride: 5,
+                min: 6
+            })
+        ));
+        assert!(matches!(
+            RGBImageView::with_stride(2, 3, 10, &data[..25]),
+            Err(Error::DataLength {
+                expected: 26,
+                found: 25,
+                ..
+            })
+        ));
+        assert!(RGBImageView::with_stride(2, 0, 10, &[]).is_ok());
+    }
+
+    #[test]
+    fn yuv_with_stride_checks_layout() {
+        // 5x3 NV12 rows are padded to 6 bytes, 3 luma rows and 2 chroma rows.
+        assert!(matches!(
+            NV12Image::with_stride(5, 3, 5, vec![0; 25]),
+            Err(Error::Stride { min: 6, .. })
+        ));
+        assert!(NV12Image::with_stride(5, 3, 8, vec![0; 40]).is_ok());
+        assert!(matches!(
+            NV12Image::with_stride(5, 3, 8, vec![0; 39]),
+            Err(Error::DataLength { expected: 40, .. })
+        ));
+
+        // 5x3 I420 with 7 byte luma rows has 4 byte chroma rows.
+        assert!(I420Image::with_stride(5, 3, 7, vec![0; 21 + 2 * 8]).is_ok());
+        assert!(matches!(
+            I420Image::with_stride(5, 3, 7, vec![0; 21 + 2 * 8 - 1]),
+            Err(Error::DataLength { expected: 37, .. })
+        ));
+        assert_eq!(
+            I420Image::new(5, 3, vec![0; 15 + 2 * 6]).unwrap().stride(),
+            5
+        );
+    }
 }
@@ -0,0 +1,327 @@
+use std::ops::Deref;
+use std::path::Path;
+
+use ::image::{ColorType, DynamicImage, ImageBuffer, Luma, LumaA, Primitive, Rgb, Rgba};
+
+use super::{Error, Image, PixelFormat};
+
+/// Pixel types of the `image` crate with a matching [`PixelFormat`].
+///
+/// Sealed, the crate reads the samples of these types as raw bytes.
+pub trait Pixel: sealed::Sealed {
+    const FORMAT: PixelFormat;
+}
+
+mod sealed {
+    pub trait Sealed: ::image::Pixel {
+        fn as_bytes(samples: &[Self::Subpixel]) -> &[u8];
+    }
+}
+
+/// Subpixel types that are plain data: no padding and every initialized value is a valid
+/// sequence of bytes.
+trait Sample: Primitive {}
+
+impl Sample for u8 {}
+impl Sample for u16 {}
+impl Sample for f32 {}
+
+macro_rules! impl_pixel {
+    ($($pixel:ty => $format:ident),* $(,)?) => {
+        $(
+            impl sealed::Sealed for $pixel {
+                fn as_bytes(samples: &[Self::Subpixel]) -> &[u8] {
+                    as_bytes(samples)
+                }
+            }
+
+            impl Pixel for $pixel {
+                const FORMAT: PixelFormat = PixelFormat::$format;
+            }
+        )*
+    };
+}
+
+impl_pixel! {
+    Rgb<u8> => Rgb8,
+    Rgba<u8> => Rgba8,
+    Luma<u8> => Gray8,
+    LumaA<u8> => GrayA8,
+    Luma<u16> => Gray16,
+    LumaA<u16> => GrayA16,
+    Rgb<u16> => Rgb16,
+    Rgba<u16> => Rgba16,
+    Rgb<f32> => Rgb32F,
+    Rgba<f32> => Rgba32F,
+}
+
+fn as_bytes<T: Sample>(samples: &[T]) -> &[u8] {
+    // SAFETY: `Sample` is only implemented for `u8`, `u16` and `f32`, which have no padding, so
+    // any initialized sample is a valid sequence of bytes.
+    unsafe { std::slice::from_raw_parts(samples.as_ptr().cast(), std::mem::size_of_val(samples)) }
+}
+
+impl<P, C> Image for ImageBuffer<P, C>
+where
+    P: Pixel,
+    C: Deref<Target = [P::Subpixel]>,
+{
+    fn width(&self) -> u32 {
+        ImageBuffer::width(self)
+    }
+
+    fn height(&self) -> u32 {
+        ImageBuffer::height(self)
+    }
+
+    fn size(&self) -> usize {
+        self.data().len()
+    }
+
+    fn data(&self) -> &[u8] {
+        P::as_bytes(self.as_raw())
+    }
+
+    fn format(&self) -> PixelFormat {
+        P::FORMAT
+    }
+}
+
+impl Image for DynamicImage {
+    fn width(&self) -> u32 {
+        DynamicImage::width(self)
+    }
+
+    fn height(&self) -> u32 {
+        DynamicImage::height(self)
+    }
+
+    fn size(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    fn data(&self) -> &[u8] {
+        self.as_bytes()
+    }
+
+    fn format(&self) -> PixelFormat {
+        match self.color() {
+            ColorType::L8 => PixelFormat::Gray8,
+            ColorType::La8 => PixelFormat::GrayA8,
+            ColorType::Rgb8 => PixelFormat::Rgb8,
+            ColorType::Rgba8 => PixelFormat::Rgba8,
+            ColorType::L16 => PixelFormat::Gray16,
+            ColorType::La16 => PixelFormat::GrayA16,
+            ColorType::Rgb16 => PixelFormat::Rgb16,
+            ColorType::Rgba16 => PixelFormat::Rgba16,
+            ColorType::Rgb32F => PixelFormat::Rgb32F,
+            ColorType::Rgba32F => PixelFormat::Rgba32F,
+            color => unreachable!("no dynamic image holds {color:?} pixels"),
+        }
+    }
+}
+
+/// Decodes the image at `path`, rotating and flipping it upright according to its EXIF
+/// orientation.
+pub(crate) fn open<P: AsRef<Path>>(path: P) -> Result<DynamicImage, Error> {
+    let bytes = std::fs::read(path)?;
+    let image = ::image::load_from_memory(&bytes)?;
+    Ok(match exif_orientation(&bytes).unwrap_or(1) {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    })
+}
+
+/// Reads the orientation tag from the EXIF segment of a JPEG file.
+fn exif_orientation(jpeg: &[u8]) -> Option<u16> {
+    const ORIENTATION: u16 = 0x0112;
+
+    if !jpeg.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let mut rest = &jpeg[2..];
+    let tiff = loop {
+        let (&[0xff, marker, hi, lo], _) = rest.split_first_chunk::<4>()? else {
+            return None;
+        };
+        // Start of scan, the metadata segments are over.
+        if marker == 0xda {
+            return None;
+        }
+        let len = u16::from_be_bytes([hi, lo]) as usize;
+        let segment = rest.get(4..2 + len)?;
+        if marker == 0xe1 {
+            if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                break tiff;
+            }
+        }
+        rest = &rest[2 + len..];
+    };
+
+    let read_u16 = |at: usize| -> Option<u16> {
+        let bytes = [*tiff.get(at)?, *tiff.get(at + 1)?];
+        Some(match &tiff[..2] {
+            b"II" => u16::from_le_bytes(bytes),
+            _ => u16::from_be_bytes(bytes),
+        })
+    };
+    let read_u32 = |at: usize| -> Option<u32> {
+        let bytes = tiff.get(at..at + 4)?.try_into().ok()?;
+        Some(match &tiff[..2] {
+            b"II" => u32::from_le_bytes(bytes),
+            _ => u32::from_be_bytes(bytes),
+        })
+    };
+
+    if !matches!(tiff.get(..2)?, b"II" | b"MM") {
+        return None;
+    }
+    let ifd = read_u32(4)? as usize;
+    (0..read_u16(ifd)? as usize)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| read_u16(entry) == Some(ORIENTATION))
+        .and_then(|entry| read_u16(entry + 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An APP1 segment holding a TIFF header whose first IFD has an unrelated tag followed by the
+    /// orientation.
+    fn exif_segment(byte_order: &[u8; 2], orientation: u16) -> Vec<u8> {
+        let u16_bytes = |v: u16| match byte_order {
+            b"II" => v.to_le_bytes(),
+            _ => v.to_be_bytes(),
+        };
+        let u32_bytes = |v: u32| match byte_order {
+            b"II" => v.to_le_bytes(),
+            _ => v.to_be_bytes(),
+        };
+        let mut tiff = byte_order.to_vec();
+        tiff.extend(u16_bytes(42));
+        tiff.extend(u32_bytes(8));
+        tiff.extend(u16_bytes(2));
+        // ImageWidth, a LONG.
+        tiff.extend([u16_bytes(0x0100), u16_bytes(4)].concat());
+        tiff.extend([u32_bytes(1), u32_bytes(640)].concat());
+        // Orientation, a SHORT padded to four bytes.
+        tiff.extend([u16_bytes(0x0112), u16_bytes(3)].concat());
+        tiff.extend(u32_bytes(1));
+        tiff.extend([u16_bytes(orientation), u16_bytes(0)].concat());
+        tiff.extend(u32_bytes(0));
+
+        let payload = [b"Exif\0\0".as_slice(), &tiff].concat();
+        let len = (payload.len() + 2) as u16;
+        [&[0xff, 0xe1], len.to_be_bytes().as_slice(), &payload].concat()
+    }
+
+    /// The start of a JPEG file: a JFIF segment, `segment` and the start of scan.
+    fn jpeg(segment: &[u8]) -> Vec<u8> {
+        let jfif = [
+            0xff, 0xe0, 0x00, 0x10, b'J', b'F', b'I', b'F', 0x00, 0x01, 0x01, 0x00, 0x00, 0x01,
+            0x00, 0x01, 0x00, 0x00,
+        ];
+        let sos = [0xff, 0xda, 0x00, 0x02];
+        [&[0xff, 0xd8], jfif.as_slice(), segment, &sos].concat()
+    }
+
+    #[test]
+    fn data_holds_samples_in_native_byte_order() {
+        let gray: ImageBuffer<Luma<u16>, _> =
+            ImageBuffer::from_raw(2, 1, vec![0x0102u16, 0xfffe]).unwrap();
+        let expected = [0x0102u16.to_ne_bytes(), 0xfffeu16.to_ne_bytes()].concat();
+        assert_eq!(Image::data(&gray), expected);
+        assert_eq!(Image::format(&gray), PixelFormat::Gray16);
+
+        let rgb: ImageBuffer<Rgb<f32>, _> =
+            ImageBuffer::from_raw(1, 1, vec![0.5f32, 1.0, -2.0]).unwrap();
+        let expected = [
+            0.5f32.to_ne_bytes(),
+            1.0f32.to_ne_bytes(),
+            (-2.0f32).to_ne_bytes(),
+        ]
+        .concat();
+        assert_eq!(Image::data(&rgb), expected);
+        assert_eq!(Image::size(&rgb), 12);
+    }
+
+    #[test]
+    fn reads_every_orientation_in_both_byte_orders() {
+        for byte_order in [b"II", b"MM"] {
+            for orientation in 1..=8 {
+                let jpeg = jpeg(&exif_segment(byte_order, orientation));
+                assert_eq!(exif_orientation(&jpeg), Some(orientation));
+            }
+        }
+    }
+
+    #[test]
+    fn missing_exif_has_no_orientation() {
+        assert_eq!(exif_orientation(&jpeg(&[])), None);
+        assert_eq!(exif_orientation(&[]), None);
+        assert_eq!(exif_orientation(b"\x89PNG\r\n\x1a\n"), None);
+
+        // An APP1 segment that isn't EXIF, such as XMP, is skipped.
+        let xmp = [0xff, 0xe1, 0x00, 0x08, b'h', b't', b't', b'p', b':', b'/'];
+        let segments = [xmp.as_slice(), &exif_segment(b"MM", 6)].concat();
+        assert_eq!(exif_orientation(&jpeg(&segments)), Some(6));
+
+        // Segments after the start of scan belong to the image data.
+        let mut after_scan = jpeg(&[]);
+        after_scan.extend(exif_segment(b"MM", 6));
+        assert_eq!(exif_orientation(&after_scan), None);
+    }
+
+    #[test]
+    fn truncated_segments_have_no_orientation() {
+        let segment = exif_segment(b"II", 3);
+        let file = jpeg(&segment);
+        let end = file.len() - 4;
+        for len in 0..end {
+            assert_eq!(exif_orientation(&file[..len]), None, "{len} bytes");
+        }
+        assert_eq!(exif_orientation(&file[..end]), Some(3));
+
+        // A segment length running past the end of the file.
+        let mut long = file.clone();
+        long[22..24].copy_from_slice(&0xfff0u16.to_be_bytes());
+        assert_eq!(exif_orientation(&long), None);
+
+        // An IFD offset pointing past the end of the segment.
+        let mut far = segment;
+        far[10 + 4] = 0xf0;
+        assert_eq!(exif_orientation(&jpeg(&far)), None);
+    }
+
+    #[test]
+    fn open_applies_orientation() {
+        use ::image::codecs::jpeg::JpegEncoder;
+
+        let mut encoded = Vec::new();
+        let pixels = ImageBuffer::from_pixel(3, 2, Rgb([200u8, 20, 20]));
+        JpegEncoder::new(&mut encoded)
+            .encode_image(&pixels)
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("clip-exif-{}.jpg", std::process::id()));
+        for (orientation, size) in [(1, (3, 2)), (3, (3, 2)), (6, (2, 3)), (8, (2, 3))] {
+            let jpeg = [
+                &encoded[..2],
+                &exif_segment(b"MM", orientation),
+                &encoded[2..],
+            ]
+            .concat();
+            std::fs::write(&path, jpeg).unwrap();
+            let image = open(&path).unwrap();
+            assert_eq!((image.width(), image.height()), size, "{orientation}");
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
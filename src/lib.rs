@@ -8,14 +8,117 @@ pub enum Error {
     ModelFail,
     #[error("failed to tokenize text")]
     Tokenize,
+    #[error("text or path contains a nul byte at position {position}")]
+    NulByte { position: usize },
+    #[error("failed to load image")]
+    ImageLoad,
     #[error("failed to preprocess image")]
     Preprocess,
+    #[error("invalid preprocessing config: {0}")]
+    PreprocessConfig(&'static str),
+    #[error("image data has {found} bytes, expected {expected} for {width}x{height} pixels")]
+    DataLength {
+        width: u32,
+        height: u32,
+        expected: usize,
+        found: usize,
+    },
+    #[error("row stride of {stride} bytes is shorter than a {width} pixel row of {min} bytes")]
+    Stride {
+        width: u32,
+        stride: usize,
+        min: usize,
+    },
+    #[error("invalid image size, expected ({expected}x{expected}) found ({width}x{height})")]
+    ImageSize {
+        expected: i32,
+        width: i32,
+        height: i32,
+    },
+    #[error("invalid image size at index {index}, expected ({expected}x{expected}) found ({width}x{height})")]
+    BatchImageSize {
+        index: usize,
+        expected: i32,
+        width: i32,
+        height: i32,
+    },
+    #[error("failed to encode text at index {index}: {source}")]
+    BatchText {
+        index: usize,
+        #[source]
+        source: Box<Error>,
+    },
+    #[error("failed to encode text")]
+    TextEncode,
+    #[error("failed to encode image")]
+    ImageEncode,
+    #[error("embedding dimension mismatch, expected {expected} found {found}")]
+    Dimension { expected: usize, found: usize },
+    #[error("embeddings come from different modalities")]
+    Modality,
+    #[error("embeddings come from different models")]
+    EmbeddingModel,
+    #[error("no embeddings to stack")]
+    EmptyEmbeddings,
+    #[error("zero-shot classification requires at least one prompt template")]
+    NoTemplates,
+    #[error("i/o error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid classifier file: {0}")]
+    InvalidClassifier(&'static str),
+    #[error("classifier was built for a different model")]
+    ModelMismatch,
+    #[error("failed to quantize model")]
+    Quantize,
+    #[error("invalid gguf file: {0}")]
+    Gguf(&'static str),
+    #[error("gguf file is missing or has an invalid value for {0}")]
+    GgufKey(&'static str),
+    #[error("invalid tokenizer vocabulary: {0}")]
+    Vocab(&'static str),
+    #[error("token id {id} is not in the vocabulary")]
+    InvalidToken { id: i32 },
+    #[error("too many tokens, expected at most {max} found {found}")]
+    TokenCount { max: usize, found: usize },
+    #[error("model pool must hold at least one context")]
+    EmptyPool,
+    #[error("inference worker stopped before completing the request")]
+    WorkerStopped,
+    #[cfg(feature = "image")]
+    #[error("failed to decode image: {0}")]
+    Decode(#[from] ::image::ImageError),
 }
 
+#[cfg(feature = "tokio")]
+mod async_model;
+mod embedding;
+pub mod gguf;
 mod image;
+#[cfg(feature = "image")]
+mod image_buffer;
 mod model;
+mod native;
 mod params;
+mod pool;
+mod preprocess;
+mod quantize;
+mod tokenizer;
+mod zero_shot;
 
-pub use self::image::{Image, RGBImage};
-pub use model::Model;
+pub use self::image::{I420Image, Image, NV12Image, PixelFormat, RGBImage, RGBImageView};
+#[cfg(feature = "tokio")]
+pub use async_model::AsyncModel;
+pub use embedding::{Embedding, Embeddings, Modality};
+pub use gguf::ModelInfo;
+#[cfg(feature = "image")]
+pub use image_buffer::Pixel;
+pub use model::{Blob, BlobBatch, Model, ModelBuilder, Verbosity};
+pub use native::NativeImage;
 pub use params::{TextParams, VisionParams};
+pub use pool::{BlobPool, ModelPool, PooledBlob, PooledModel};
+pub use preprocess::{FilterType, PreprocessConfig, PreprocessOptions, ResizeMode};
+pub use quantize::{quantize, QuantType};
+pub use tokenizer::{Tokenizer, Tokens};
+pub use zero_shot::{
+    LabelScore, ZeroShotClassifier, ZeroShotOptions, DEFAULT_LOGIT_SCALE, DEFAULT_TEMPLATE,
+};
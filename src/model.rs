@@ -1,28 +1,193 @@
+use std::borrow::Borrow;
 use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
-use ndarray::{ArrayView, ArrayViewMut, Zip};
+use ndarray::Array2;
 
-use super::{Error, Image, TextParams, VisionParams};
+use super::gguf::Header;
+use super::params::ModelParams;
+use super::preprocess::{self, PreprocessConfig, PreprocessOptions};
+use super::quantize::path_to_cstring;
+use super::tokenizer::{Tokens, Vocab};
+use super::zero_shot::{LabelScore, ZeroShotClassifier, ZeroShotOptions};
+use super::{Embedding, Embeddings, Error, Image, Modality, NativeImage, TextParams, VisionParams};
 
 #[repr(i32)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub enum Verbosity {
     Minimum = 0,
+    #[default]
     Default = 1,
     Maximum = 2,
 }
 
-impl Default for Verbosity {
-    fn default() -> Self {
-        Self::Default
+/// Where a [`ModelBuilder`] reads the model file from.
+#[derive(Debug, Clone)]
+enum Source {
+    Path(PathBuf),
+    /// Bytes copied to a file, shared by the clones of a builder.
+    Staged(Arc<StagedFile>),
+}
+
+/// Model bytes copied to a file clip.cpp, which only loads models from a path, can open.
+#[derive(Debug)]
+enum StagedFile {
+    /// An anonymous file in memory, opened through `/proc/self/fd` and freed with its descriptor.
+    #[cfg(target_os = "linux")]
+    Memfd { _file: File, path: PathBuf },
+    /// A private file in the temporary directory, removed on drop.
+    Temp(PathBuf),
+}
+
+impl StagedFile {
+    /// Stages `bytes` in a memfd where available, in the temporary directory otherwise.
+    fn write(bytes: &[u8]) -> Result<Self, Error> {
+        #[cfg(target_os = "linux")]
+        if let Ok(file) = Self::memfd(bytes) {
+            return Ok(file);
+        }
+        let names = std::iter::repeat_with(|| {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+            format!("clip-{}-{id}.gguf", std::process::id())
+        });
+        Self::temp(&std::env::temp_dir(), names.take(100), bytes)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn memfd(bytes: &[u8]) -> std::io::Result<Self> {
+        use std::os::fd::FromRawFd;
+
+        let fd = unsafe { libc::memfd_create(c"clip-model".as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: `fd` was just created and nothing else owns it.
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        file.write_all(bytes)?;
+        // clip.cpp can't open the file without procfs.
+        let path = PathBuf::from(format!("/proc/self/fd/{fd}"));
+        std::fs::metadata(&path)?;
+        Ok(Self::Memfd { _file: file, path })
+    }
+
+    /// Writes `bytes` to the first of `names` in `dir` that doesn't exist yet, skipping those
+    /// left behind by other processes.
+    fn temp<I>(dir: &Path, names: I, bytes: &[u8]) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let mut last = None;
+        for name in names {
+            let path = dir.join(name);
+            let mut file = match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+            {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    last = Some(e);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let staged = Self::Temp(path);
+            file.write_all(bytes)?;
+            return Ok(staged);
+        }
+        Err(last
+            .unwrap_or_else(|| std::io::ErrorKind::AlreadyExists.into())
+            .into())
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            #[cfg(target_os = "linux")]
+            Self::Memfd { path, .. } => path,
+            Self::Temp(path) => path,
+        }
+    }
+}
+
+impl Drop for StagedFile {
+    fn drop(&mut self) {
+        if let Self::Temp(path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Image mean and standard deviation stored in the model file, which clip.cpp normalizes with.
+fn file_normalization(ctx: *mut clip_cpp_sys::clip_ctx) -> ([f32; 3], [f32; 3]) {
+    unsafe {
+        let mean = clip_cpp_sys::clip_get_image_mean(ctx);
+        let std = clip_cpp_sys::clip_get_image_std(ctx);
+        (
+            [*mean, *mean.add(1), *mean.add(2)],
+            [*std, *std.add(1), *std.add(2)],
+        )
     }
 }
 
+/// Reads the vocabulary used by [`Model::detokenize`].
+///
+/// Only detokenization depends on it, so a file clip.cpp loads but the metadata reader rejects
+/// still builds, without a vocabulary.
+fn read_vocab(header: Result<Header, Error>) -> Option<Vocab> {
+    header.and_then(|header| Vocab::from_header(&header)).ok()
+}
+
+/// Calls `encode` on chunks of at most `chunk` items along with room for the `dim` floats per
+/// item of their rows, yielding the rows of each chunk in input order or the chunk's error.
+fn encode_chunks<'a, T, F>(
+    items: &'a mut [T],
+    dim: usize,
+    chunk: usize,
+    mut encode: F,
+) -> impl Iterator<Item = Result<Vec<f32>, Error>> + 'a
+where
+    F: FnMut(&mut [T], &mut [f32]) -> bool + 'a,
+{
+    items.chunks_mut(chunk.max(1)).map(move |items| {
+        let mut out = vec![0f32; items.len() * dim];
+        if encode(items, &mut out) {
+            Ok(out)
+        } else {
+            Err(Error::ImageEncode)
+        }
+    })
+}
+
+/// Row `row` of the output of [`encode_chunks`], [`Error::ImageEncode`] if its chunk failed.
+fn chunk_row(
+    chunks: &[Result<Vec<f32>, Error>],
+    chunk: usize,
+    dim: usize,
+    row: usize,
+) -> Result<&[f32], Error> {
+    match &chunks[row / chunk] {
+        Ok(rows) => Ok(&rows[row % chunk * dim..][..dim]),
+        Err(_) => Err(Error::ImageEncode),
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ModelBuilder {
     verbosity: Verbosity,
-    path: PathBuf,
+    source: Source,
     threads: i32,
+    max_batch_size: Option<usize>,
+    mean: Option<[f32; 3]>,
+    std: Option<[f32; 3]>,
+    preprocess: PreprocessOptions,
 }
 
 impl ModelBuilder {
@@ -36,14 +201,60 @@ impl ModelBuilder {
         self
     }
 
+    /// Splits image batches into chunks of at most `size` images, each encoded with its own call
+    /// into clip.cpp, to bound the memory used by large batches. See
+    /// [`VisionParams::batch_memory`] to pick a size. Batches are not split by default.
+    pub fn max_batch_size(mut self, size: usize) -> Self {
+        self.max_batch_size = Some(size.max(1));
+        self
+    }
+
+    /// Per-channel image mean, overriding the one stored in the model file.
+    ///
+    /// [`build`](Self::build) fails with [`Error::PreprocessConfig`] unless every channel is
+    /// finite.
+    pub fn image_mean(mut self, mean: [f32; 3]) -> Self {
+        self.mean = Some(mean);
+        self
+    }
+
+    /// Per-channel image standard deviation, overriding the one stored in the model file.
+    ///
+    /// [`build`](Self::build) fails with [`Error::PreprocessConfig`] unless every channel is
+    /// finite and non-zero.
+    pub fn image_std(mut self, std: [f32; 3]) -> Self {
+        self.std = Some(std);
+        self
+    }
+
+    /// Resize mode, filter and colors used by the preprocessing methods that take no options,
+    /// the reference CLIP pipeline by default.
+    pub fn preprocess(mut self, options: PreprocessOptions) -> Self {
+        self.preprocess = options;
+        self
+    }
+
     pub fn build(self) -> Result<Model, Error> {
-        if !self.path.exists() {
-            return Err(Error::PathNotFound);
+        if let Some(mean) = &self.mean {
+            preprocess::check_mean(mean)?;
         }
-        let path = {
-            use std::os::unix::ffi::OsStrExt;
-            CString::new(self.path.as_os_str().as_bytes()).unwrap()
+        if let Some(std) = &self.std {
+            preprocess::check_std(std)?;
+        }
+        let path = match &self.source {
+            Source::Path(path) => {
+                if !path.exists() {
+                    return Err(Error::PathNotFound);
+                }
+                path
+            }
+            Source::Staged(file) => file.path(),
         };
+        self.load(path, read_vocab(Header::read_file(path)))
+    }
+
+    fn load(&self, path: &Path, vocab: Option<Vocab>) -> Result<Model, Error> {
+        let path = path_to_cstring(path)?;
         let ctx = unsafe { clip_cpp_sys::clip_model_load(path.as_ptr(), self.verbosity as i32) };
         let ctx = match std::ptr::NonNull::new(ctx) {
             Some(ctx) => ctx,
@@ -52,13 +263,14 @@ impl ModelBuilder {
 
         let text_params = unsafe { *clip_cpp_sys::clip_get_text_hparams(ctx.as_ptr()) };
         let vision_params = unsafe { *clip_cpp_sys::clip_get_vision_hparams(ctx.as_ptr()) };
-        let mean = unsafe {
-            let mean = clip_cpp_sys::clip_get_image_mean(ctx.as_ptr());
-            std::slice::from_raw_parts(mean, 3).to_vec()
-        };
-        let std = unsafe {
-            let std = clip_cpp_sys::clip_get_image_std(ctx.as_ptr());
-            std::slice::from_raw_parts(std, 3).to_vec()
+        let (file_mean, file_std) = file_normalization(ctx.as_ptr());
+        let mean = self.mean.unwrap_or(file_mean);
+        let std = self.std.unwrap_or(file_std);
+        let preprocess = PreprocessConfig {
+            image_size: vision_params.image_size,
+            mean,
+            std,
+            options: self.preprocess.clone(),
         };
 
         Ok(Model {
@@ -66,27 +278,99 @@ impl ModelBuilder {
             text_params: text_params.into(),
             vision_params: vision_params.into(),
             threads: self.threads,
-            mean,
-            std,
+            max_batch_size: self.max_batch_size,
+            vocab,
+            preprocess,
         })
     }
 }
 
+/// A preprocessed image ready to be encoded.
+///
+/// Blobs can be reused with [`Model::preprocess_into`], which keeps their allocation.
 #[derive(Debug)]
-pub struct Tokens {
-    tokens: clip_cpp_sys::clip_tokens,
+pub struct Blob {
+    image: clip_cpp_sys::clip_image_f32,
+    data: Vec<f32>,
 }
 
-impl AsRef<clip_cpp_sys::clip_tokens> for Tokens {
-    fn as_ref(&self) -> &clip_cpp_sys::clip_tokens {
-        &self.tokens
+// `image` only points into `data`, which the blob owns.
+unsafe impl Send for Blob {}
+unsafe impl Sync for Blob {}
+
+impl Default for Blob {
+    fn default() -> Self {
+        Self::new(0, 0, Vec::new())
     }
 }
 
-#[derive(Debug)]
-pub struct Blob {
-    image: clip_cpp_sys::clip_image_f32,
-    _data: Vec<f32>,
+impl Blob {
+    fn new(width: i32, height: i32, mut data: Vec<f32>) -> Self {
+        let image = clip_cpp_sys::clip_image_f32 {
+            nx: width as _,
+            ny: height as _,
+            size: data.len(),
+            data: data.as_mut_ptr(),
+        };
+        Self { image, data }
+    }
+
+    /// Resizes the buffer for a `size x size` input, reusing its allocation.
+    pub(crate) fn reset(&mut self, size: i32) -> &mut [f32] {
+        self.data.resize((size * size * 3) as usize, 0.0);
+        self.image = clip_cpp_sys::clip_image_f32 {
+            nx: size as _,
+            ny: size as _,
+            size: self.data.len(),
+            data: self.data.as_mut_ptr(),
+        };
+        &mut self.data
+    }
+
+    /// Normalized model input, interleaved RGB values row by row.
+    pub fn as_slice(&self) -> &[f32] {
+        &self.data
+    }
+}
+
+/// Preprocessed images stored back to back in a single allocation, see
+/// [`Model::preprocess_batch_into`].
+#[derive(Debug, Clone, Default)]
+pub struct BlobBatch {
+    data: Vec<f32>,
+    image_size: i32,
+    len: usize,
+}
+
+impl BlobBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes every image, keeping the allocation.
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.len = 0;
+    }
+
+    /// Normalized model inputs as a `(len, size, size, 3)` tensor.
+    pub fn as_slice(&self) -> &[f32] {
+        &self.data
+    }
+
+    /// Normalized model input of the image at `index`.
+    pub fn get(&self, index: usize) -> Option<&[f32]> {
+        let n = self.data.len().checked_div(self.len)?;
+        self.data.get(index * n..(index + 1) * n)
+    }
 }
 
 impl AsRef<clip_cpp_sys::clip_image_f32> for Blob {
@@ -98,10 +382,11 @@ impl AsRef<clip_cpp_sys::clip_image_f32> for Blob {
 pub struct Model {
     ctx: std::ptr::NonNull<clip_cpp_sys::clip_ctx>,
     threads: i32,
+    max_batch_size: Option<usize>,
     text_params: TextParams,
     vision_params: VisionParams,
-    mean: Vec<f32>,
-    std: Vec<f32>,
+    vocab: Option<Vocab>,
+    preprocess: PreprocessConfig,
 }
 
 unsafe impl Send for Model {}
@@ -111,10 +396,38 @@ impl Model {
         ModelBuilder {
             verbosity: Verbosity::default(),
             threads: 1,
-            path: PathBuf::from(model_path.as_ref()),
+            source: Source::Path(PathBuf::from(model_path.as_ref())),
+            max_batch_size: None,
+            mean: None,
+            std: None,
+            preprocess: PreprocessOptions::default(),
         }
     }
 
+    /// Loads the model from a GGUF file held in memory, such as a `Vec<u8>`, a borrowed slice, an
+    /// `Arc<[u8]>` or `include_bytes!` data.
+    ///
+    /// clip.cpp can only load models from a path, so the bytes are copied right away. On Linux the
+    /// copy is an anonymous in-memory file (`memfd_create`), which needs no writable directory but
+    /// takes as much memory as the model, so the caller's bytes can be dropped once this returns.
+    /// Elsewhere, or if that fails, the bytes go to a private file in the temporary directory,
+    /// which takes as much disk space. The builder and its clones, such as those made by
+    /// [`ModelPool`](crate::ModelPool), share the copy and every [`build`](ModelBuilder::build)
+    /// loads from it. It is freed once the last of them is dropped, models already built don't
+    /// need it. A memory-mapped file can be passed as a slice too, though if it is backed by a
+    /// file on disk, passing that path to [`builder`](Self::builder) avoids the copy.
+    pub fn builder_from_bytes<B: AsRef<[u8]>>(bytes: B) -> Result<ModelBuilder, Error> {
+        Ok(ModelBuilder {
+            verbosity: Verbosity::default(),
+            threads: 1,
+            source: Source::Staged(Arc::new(StagedFile::write(bytes.as_ref())?)),
+            max_batch_size: None,
+            mean: None,
+            std: None,
+            preprocess: PreprocessOptions::default(),
+        })
+    }
+
     pub fn text_params(&self) -> &TextParams {
         &self.text_params
     }
@@ -123,137 +436,597 @@ impl Model {
         &self.vision_params
     }
 
+    /// Identifies the model in the embeddings it produces.
+    pub(crate) fn params(&self) -> ModelParams {
+        ModelParams {
+            text: self.text_params,
+            vision: self.vision_params,
+        }
+    }
+
+    /// Preprocessing applied by the methods that take no options, for logging.
+    pub fn preprocess_config(&self) -> &PreprocessConfig {
+        &self.preprocess
+    }
+
+    /// Largest number of images encoded in one call into clip.cpp, if batches are split.
+    pub fn max_batch_size(&self) -> Option<usize> {
+        self.max_batch_size
+    }
+
     pub fn tokenize<T: AsRef<str>>(&self, text: T) -> Result<Tokens, Error> {
         let mut tokens: clip_cpp_sys::clip_tokens = unsafe { std::mem::zeroed() };
 
-        let text = CString::new(text.as_ref())
-            .expect("Failed to convert text into cstring: contained null byte?");
-        unsafe {
+        let text = CString::new(text.as_ref()).map_err(|e| Error::NulByte {
+            position: e.nul_position(),
+        })?;
+        let ids = unsafe {
             if !clip_cpp_sys::clip_tokenize(self.ctx.as_ptr(), text.as_ptr(), &mut tokens) {
                 return Err(Error::Tokenize);
             }
-        }
+            std::slice::from_raw_parts(tokens.data, tokens.size).to_vec()
+        };
 
-        Ok(Tokens { tokens })
+        let mut tokens = Tokens::from_ids(ids);
+        tokens.truncate(self.text_params.positions() as usize);
+        Ok(tokens)
     }
 
-    pub fn encode_tokens(&self, tokens: &Tokens, normalize: bool) -> Vec<f32> {
-        let mut encode = vec![0f32; self.vision_params.projection_dim() as usize];
-        unsafe {
+    /// Decodes token ids back into text using the vocabulary stored in the model file.
+    ///
+    /// Fails with [`Error::Vocab`] if the vocabulary could not be read from the model file.
+    pub fn detokenize(&self, tokens: &Tokens) -> Result<String, Error> {
+        self.vocab
+            .as_ref()
+            .ok_or(Error::Vocab(
+                "vocabulary could not be read from the model file",
+            ))?
+            .decode(tokens.ids())
+    }
+
+    fn encode_tokens_into(
+        &self,
+        tokens: &Tokens,
+        normalize: bool,
+        encode: &mut [f32],
+    ) -> Result<(), Error> {
+        let max = self.text_params.positions() as usize;
+        if tokens.len() > max {
+            return Err(Error::TokenCount {
+                max,
+                found: tokens.len(),
+            });
+        }
+        let n_vocab = self.text_params.vocab();
+        if let Some(&id) = tokens.ids().iter().find(|&&id| id < 0 || id >= n_vocab) {
+            return Err(Error::InvalidToken { id });
+        }
+
+        let ctokens = clip_cpp_sys::clip_tokens {
+            data: tokens.ids().as_ptr() as *mut _,
+            size: tokens.len(),
+        };
+        let ok = unsafe {
             clip_cpp_sys::clip_text_encode(
                 self.ctx.as_ptr(),
                 self.threads,
-                &tokens.tokens,
+                &ctokens,
                 encode.as_mut_ptr(),
                 normalize,
-            );
+            )
+        };
+        if !ok {
+            return Err(Error::TextEncode);
         }
 
-        encode
+        Ok(())
+    }
+
+    pub fn try_encode_tokens(&self, tokens: &Tokens, normalize: bool) -> Result<Embedding, Error> {
+        let mut encode = vec![0f32; self.vision_params.projection_dim() as usize];
+        self.encode_tokens_into(tokens, normalize, &mut encode)?;
+
+        Ok(Embedding::new(
+            encode,
+            normalize,
+            Modality::Text,
+            self.params(),
+        ))
     }
 
-    pub fn encode_text<T: AsRef<str>>(&self, text: T, normalize: bool) -> Result<Vec<f32>, Error> {
+    #[deprecated(note = "use `try_encode_tokens` instead")]
+    pub fn encode_tokens(&self, tokens: &Tokens, normalize: bool) -> Vec<f32> {
+        self.try_encode_tokens(tokens, normalize)
+            .map(Embedding::into_vec)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub fn encode_text<T: AsRef<str>>(&self, text: T, normalize: bool) -> Result<Embedding, Error> {
         let tokens = self.tokenize(text)?;
-        Ok(self.encode_tokens(&tokens, normalize))
+        self.try_encode_tokens(&tokens, normalize)
     }
 
-    pub fn preprocess_image<I: Image>(&self, image: I) -> Result<Blob, Error> {
-        let mat = ArrayView::from_shape(
-            (image.height() as usize, image.width() as usize, 3),
-            image.data(),
-        )
-        .unwrap();
+    /// Encodes a batch of tokenized texts into a `(texts, projection_dim)` array, one row per
+    /// input in iteration order.
+    ///
+    /// clip.cpp has no batched text encoder, each input is encoded by its own call straight into
+    /// its row of the output. A failing input is reported as [`Error::BatchText`] with its index.
+    pub fn encode_token_batch<'a, T: IntoIterator<Item = &'a Tokens>>(
+        &self,
+        tokens: T,
+        normalize: bool,
+    ) -> Result<Embeddings, Error> {
+        self.encode_token_batch_from(tokens, 0, normalize)
+    }
 
-        let mut dest = unsafe {
-            let mut v = Vec::<f32>::with_capacity(image.data().len());
-            v.set_len(image.data().len());
-            v
-        };
+    /// Same as [`encode_token_batch`](Self::encode_token_batch), numbering inputs from `first`.
+    fn encode_token_batch_from<'a, T: IntoIterator<Item = &'a Tokens>>(
+        &self,
+        tokens: T,
+        first: usize,
+        normalize: bool,
+    ) -> Result<Embeddings, Error> {
+        let dim = self.vision_params.projection_dim() as usize;
+        let mut encode = Vec::new();
+        for (index, tokens) in (first..).zip(tokens) {
+            encode.resize(encode.len() + dim, 0f32);
+            let at = encode.len() - dim;
+            self.encode_tokens_into(tokens, normalize, &mut encode[at..])
+                .map_err(|e| Error::BatchText {
+                    index,
+                    source: Box::new(e),
+                })?;
+        }
 
-        let dest_av = ArrayViewMut::from_shape(
-            (image.height() as usize, image.width() as usize, 3),
-            &mut dest,
-        )
-        .unwrap();
+        let encode = Array2::from_shape_vec((encode.len() / dim, dim), encode).unwrap();
+        Ok(Embeddings::new(
+            encode,
+            normalize,
+            Modality::Text,
+            self.params(),
+        ))
+    }
 
-        Zip::indexed(dest_av)
-            .and(&mat)
-            .for_each(|(_, _, c), im, mat| {
-                *im = ((*mat as f32) / 255.0 - self.mean[c]) / self.std[c]
-            });
+    /// Tokenizes and encodes a batch of texts into a `(texts, projection_dim)` array, one row
+    /// per input in iteration order.
+    ///
+    /// A failing input is reported as [`Error::BatchText`] with its index. See
+    /// [`encode_text_chunks`](Self::encode_text_chunks) to process large jobs piece by piece.
+    pub fn encode_texts<T>(&self, texts: T, normalize: bool) -> Result<Embeddings, Error>
+    where
+        T: IntoIterator,
+        T::Item: AsRef<str>,
+    {
+        self.encode_texts_from(texts, 0, normalize)
+    }
 
-        let cimage = clip_cpp_sys::clip_image_f32 {
-            nx: image.width() as _,
-            ny: image.height() as _,
-            size: image.size(),
-            data: dest.as_mut_ptr(),
-        };
+    /// Same as [`encode_texts`](Self::encode_texts), numbering inputs from `first`.
+    fn encode_texts_from<T>(
+        &self,
+        texts: T,
+        first: usize,
+        normalize: bool,
+    ) -> Result<Embeddings, Error>
+    where
+        T: IntoIterator,
+        T::Item: AsRef<str>,
+    {
+        let tokens = (first..)
+            .zip(texts)
+            .map(|(index, text)| {
+                self.tokenize(text).map_err(|e| Error::BatchText {
+                    index,
+                    source: Box::new(e),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.encode_token_batch_from(&tokens, first, normalize)
+    }
 
-        Ok(Blob {
-            image: cimage,
-            _data: dest,
+    /// Tokenizes and encodes `texts` in chunks of at most `chunk_size` texts, yielding one
+    /// `(chunk, projection_dim)` array per chunk in order.
+    ///
+    /// Only one chunk is held in memory at a time, so results of large jobs can be written out
+    /// while the remaining texts are still being encoded. Errors carry the index of the failing
+    /// text within `texts`, iteration can continue with the next chunk.
+    pub fn encode_text_chunks<'a, T>(
+        &'a self,
+        texts: T,
+        chunk_size: usize,
+        normalize: bool,
+    ) -> impl Iterator<Item = Result<Embeddings, Error>> + 'a
+    where
+        T: IntoIterator,
+        T::IntoIter: 'a,
+        T::Item: AsRef<str>,
+    {
+        let chunk_size = chunk_size.max(1);
+        let mut texts = texts.into_iter().fuse();
+        let mut first = 0;
+        std::iter::from_fn(move || {
+            let chunk = texts.by_ref().take(chunk_size).collect::<Vec<_>>();
+            if chunk.is_empty() {
+                return None;
+            }
+            let result = self.encode_texts_from(&chunk, first, normalize);
+            first += chunk.len();
+            Some(result)
         })
     }
 
-    pub fn encode_image(&self, blob: &Blob, normalize: bool) -> Vec<f32> {
+    /// Resizes, crops and normalizes `image` with the model's preprocessing.
+    pub fn preprocess_image<I: Image>(&self, image: I) -> Result<Blob, Error> {
+        self.preprocess_image_with(image, &self.preprocess.options)
+    }
+
+    /// Resizes and normalizes `image` into the model input size as described by `options`.
+    pub fn preprocess_image_with<I: Image>(
+        &self,
+        image: I,
+        options: &PreprocessOptions,
+    ) -> Result<Blob, Error> {
+        let mut blob = Blob::default();
+        self.preprocess_into_with(image, options, &mut blob)?;
+        Ok(blob)
+    }
+
+    /// Same as [`preprocess_image`](Self::preprocess_image), reusing the allocation of `blob`.
+    pub fn preprocess_into<I: Image>(&self, image: I, blob: &mut Blob) -> Result<(), Error> {
+        self.preprocess_into_with(image, &self.preprocess.options, blob)
+    }
+
+    /// Same as [`preprocess_image_with`](Self::preprocess_image_with), reusing the allocation of
+    /// `blob`.
+    pub fn preprocess_into_with<I: Image>(
+        &self,
+        image: I,
+        options: &PreprocessOptions,
+        blob: &mut Blob,
+    ) -> Result<(), Error> {
+        self.preprocess.preprocess_into_with(image, options, blob)
+    }
+
+    /// Preprocesses `images` with the model's preprocessing into `batch`, replacing its
+    /// contents and reusing its allocation.
+    pub fn preprocess_batch_into<T>(&self, images: T, batch: &mut BlobBatch) -> Result<(), Error>
+    where
+        T: IntoIterator,
+        T::Item: Image,
+    {
+        self.preprocess_batch_into_with(images, &self.preprocess.options, batch)
+    }
+
+    /// Preprocesses `images` as described by `options` into `batch`, replacing its contents and
+    /// reusing its allocation.
+    ///
+    /// On error `batch` holds the images preceding the one that failed.
+    pub fn preprocess_batch_into_with<T>(
+        &self,
+        images: T,
+        options: &PreprocessOptions,
+        batch: &mut BlobBatch,
+    ) -> Result<(), Error>
+    where
+        T: IntoIterator,
+        T::Item: Image,
+    {
+        let image_size = self.vision_params.image_size();
+        let n = (image_size * image_size * 3) as usize;
+        batch.clear();
+        batch.image_size = image_size;
+        for image in images {
+            let at = batch.data.len();
+            batch.data.resize(at + n, 0.0);
+            let dest = &mut batch.data[at..];
+            if let Err(e) = self.preprocess.write(&image, options, dest) {
+                batch.data.truncate(at);
+                return Err(e);
+            }
+            batch.len += 1;
+        }
+        Ok(())
+    }
+
+    /// Resizes, crops and normalizes `image` with clip.cpp's own preprocessing, producing the
+    /// same input as the upstream C++ tools.
+    ///
+    /// The resize mode and filter set with [`ModelBuilder::preprocess`] don't apply, clip.cpp
+    /// always resizes and crops the same way. The output is normalized with
+    /// [`preprocess_config`](Self::preprocess_config), including the
+    /// [`image_mean`](ModelBuilder::image_mean) and [`image_std`](ModelBuilder::image_std)
+    /// overrides, rather than with the values in the model file clip.cpp uses.
+    pub fn preprocess_native(&self, image: &NativeImage) -> Result<Blob, Error> {
+        let (mean, std) = file_normalization(self.ctx.as_ptr());
+        unsafe {
+            let res = clip_cpp_sys::clip_image_f32_make();
+            if res.is_null() {
+                return Err(Error::Preprocess);
+            }
+            let ok = clip_cpp_sys::clip_image_preprocess(self.ctx.as_ptr(), image.as_ptr(), res);
+            let blob = (ok && !(*res).data.is_null()).then(|| {
+                let mut data = std::slice::from_raw_parts((*res).data, (*res).size).to_vec();
+                self.preprocess.renormalize(&mut data, mean, std);
+                Blob::new((*res).nx, (*res).ny, data)
+            });
+            clip_cpp_sys::clip_image_f32_free(res);
+            blob.ok_or(Error::Preprocess)
+        }
+    }
+
+    /// Fails with [`Error::ImageSize`] unless `blob` has the model input size.
+    fn check_blob(&self, blob: &Blob) -> Result<(), Error> {
         let image_size = self.vision_params.image_size();
         if blob.image.nx != image_size || blob.image.ny != image_size {
-            panic!(
-                "invalid image size, expected ({image_size}x{image_size}) found ({}x{})",
-                blob.image.nx, blob.image.ny
-            );
+            return Err(Error::ImageSize {
+                expected: image_size,
+                width: blob.image.nx,
+                height: blob.image.ny,
+            });
         }
+        Ok(())
+    }
+
+    pub fn try_encode_image(&self, blob: &Blob, normalize: bool) -> Result<Embedding, Error> {
+        self.check_blob(blob)?;
         let mut encode = vec![0f32; self.vision_params.projection_dim() as usize];
-        unsafe {
+        let ok = unsafe {
             clip_cpp_sys::clip_image_encode(
                 self.ctx.as_ptr(),
                 self.threads,
                 &blob.image as *const _ as *mut _,
                 encode.as_mut_ptr(),
                 normalize,
-            );
+            )
+        };
+        if !ok {
+            return Err(Error::ImageEncode);
         }
 
-        encode
+        Ok(Embedding::new(
+            encode,
+            normalize,
+            Modality::Image,
+            self.params(),
+        ))
+    }
+
+    #[deprecated(note = "use `try_encode_image` instead")]
+    pub fn encode_image(&self, blob: &Blob, normalize: bool) -> Vec<f32> {
+        self.try_encode_image(blob, normalize)
+            .map(Embedding::into_vec)
+            .unwrap_or_else(|e| panic!("{e}"))
     }
 
     pub fn preprocess_images<T>(&self, images: T) -> Result<Vec<Blob>, Error>
+    where
+        T: IntoIterator,
+        T::Item: Image,
+    {
+        self.preprocess_images_with(images, &self.preprocess.options)
+    }
+
+    pub fn preprocess_images_with<T>(
+        &self,
+        images: T,
+        options: &PreprocessOptions,
+    ) -> Result<Vec<Blob>, Error>
     where
         T: IntoIterator,
         T::Item: Image,
     {
         let blobs = images
             .into_iter()
-            .map(|i| self.preprocess_image(i))
+            .map(|i| self.preprocess_image_with(i, options))
             .collect::<Result<Vec<_>, _>>()?;
         Ok(blobs)
     }
 
-    pub fn encode_images<'a, T: IntoIterator<Item = &'a Blob>>(
+    /// Preprocesses every image of `images` with the model's preprocessing, returning one result
+    /// per image in input order instead of stopping at the first failure.
+    pub fn preprocess_images_each<T>(&self, images: T) -> Vec<Result<Blob, Error>>
+    where
+        T: IntoIterator,
+        T::Item: Image,
+    {
+        images
+            .into_iter()
+            .map(|image| self.preprocess_image(image))
+            .collect()
+    }
+
+    /// Preprocesses `images` with the model's preprocessing in parallel on the rayon thread
+    /// pool, returning one result per image in input order.
+    #[cfg(feature = "rayon")]
+    pub fn par_preprocess_images<T>(&self, images: T) -> Vec<Result<Blob, Error>>
+    where
+        T: rayon::iter::IntoParallelIterator,
+        T::Item: Image,
+    {
+        self.par_preprocess_images_with(images, &self.preprocess.options)
+    }
+
+    /// Preprocesses `images` as described by `options` in parallel on the rayon thread pool,
+    /// returning one result per image in input order.
+    #[cfg(feature = "rayon")]
+    pub fn par_preprocess_images_with<T>(
+        &self,
+        images: T,
+        options: &PreprocessOptions,
+    ) -> Vec<Result<Blob, Error>>
+    where
+        T: rayon::iter::IntoParallelIterator,
+        T::Item: Image,
+    {
+        use rayon::iter::ParallelIterator;
+
+        // The context is not shared between threads, only the preprocessing config is.
+        let config = &self.preprocess;
+        images
+            .into_par_iter()
+            .map(|image| config.preprocess_image_with(image, options))
+            .collect()
+    }
+
+    /// Decodes the image files at `paths`, turns them upright according to their EXIF
+    /// orientation and preprocesses them with the model's preprocessing, all in parallel on the
+    /// rayon thread pool. Returns one result per file in input order.
+    #[cfg(all(feature = "rayon", feature = "image"))]
+    pub fn par_preprocess_files<T>(&self, paths: T) -> Vec<Result<Blob, Error>>
+    where
+        T: rayon::iter::IntoParallelIterator,
+        T::Item: AsRef<Path>,
+    {
+        use rayon::iter::ParallelIterator;
+
+        let config = &self.preprocess;
+        paths
+            .into_par_iter()
+            .map(|path| config.preprocess_image(super::image_buffer::open(path)?))
+            .collect()
+    }
+
+    pub fn try_encode_images<'a, T: IntoIterator<Item = &'a Blob>>(
         &self,
         images: T,
         normalize: bool,
-    ) -> Vec<Vec<f32>> {
+    ) -> Result<Embeddings, Error> {
         let image_size = self.vision_params.image_size();
-        let mut images = images
+        let images = images
             .into_iter()
-            .map(|blob| {
+            .enumerate()
+            .map(|(index, blob)| {
                 if blob.image.nx != image_size || blob.image.ny != image_size {
-                    panic!(
-                        "invalid image size, expected ({image_size}x{image_size}) found ({}x{})",
-                        blob.image.nx, blob.image.ny
-                    );
+                    return Err(Error::BatchImageSize {
+                        index,
+                        expected: image_size,
+                        width: blob.image.nx,
+                        height: blob.image.ny,
+                    });
                 }
-                blob.image
+                Ok(blob.image)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.batch_encode(images, normalize)
+    }
+
+    /// Encodes every image of `batch` as one batch, one row per image in batch order.
+    pub fn encode_batch(&self, batch: &BlobBatch, normalize: bool) -> Result<Embeddings, Error> {
+        let image_size = self.vision_params.image_size();
+        if !batch.is_empty() && batch.image_size != image_size {
+            return Err(Error::ImageSize {
+                expected: image_size,
+                width: batch.image_size,
+                height: batch.image_size,
+            });
+        }
+        let n = (image_size * image_size * 3) as usize;
+        let images = batch
+            .data
+            .chunks_exact(n)
+            .map(|data| clip_cpp_sys::clip_image_f32 {
+                nx: image_size as _,
+                ny: image_size as _,
+                size: n,
+                data: data.as_ptr() as *mut _,
             })
+            .collect();
+        self.batch_encode(images, normalize)
+    }
+
+    /// Encodes the successfully preprocessed blobs of `blobs` as one batch, returning one result
+    /// per input in input order.
+    ///
+    /// Failed inputs keep their error, blobs of the wrong size fail with [`Error::ImageSize`]
+    /// without affecting the rest of the batch. With a
+    /// [`max_batch_size`](ModelBuilder::max_batch_size), a chunk clip.cpp fails to encode only
+    /// fails its own images with [`Error::ImageEncode`]. Pairs with
+    /// [`preprocess_images_each`](Self::preprocess_images_each).
+    pub fn encode_preprocessed<T, B>(
+        &self,
+        blobs: T,
+        normalize: bool,
+    ) -> Vec<Result<Embedding, Error>>
+    where
+        T: IntoIterator<Item = Result<B, Error>>,
+        B: Borrow<Blob>,
+    {
+        // Owned blobs must outlive the batch call, which only borrows their data.
+        let blobs = blobs
+            .into_iter()
+            .map(|blob| blob.and_then(|b| self.check_blob(b.borrow()).map(|_| b)))
             .collect::<Vec<_>>();
-        let mut encode = vec![0f32; images.len() * (self.vision_params.projection_dim() as usize)];
+        let mut images = blobs
+            .iter()
+            .filter_map(|blob| blob.as_ref().ok())
+            .map(|blob| blob.borrow().image)
+            .collect::<Vec<_>>();
+        let dim = self.vision_params.projection_dim() as usize;
+        let chunk = self.max_batch_size.unwrap_or(images.len()).max(1);
+        let chunks = encode_chunks(&mut images, dim, chunk, |images, out| {
+            self.encode_chunk(images, out, normalize)
+        })
+        .collect::<Vec<_>>();
+
+        let mut rows = 0..;
+        blobs
+            .into_iter()
+            .map(|blob| {
+                blob?;
+                let row = rows.next().unwrap();
+                Ok(Embedding::new(
+                    chunk_row(&chunks, chunk, dim, row)?.to_vec(),
+                    normalize,
+                    Modality::Image,
+                    self.params(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Preprocesses `images` with the model's preprocessing and encodes the ones that succeed
+    /// as one batch, returning one result per image in input order.
+    pub fn encode_images_each<T>(&self, images: T, normalize: bool) -> Vec<Result<Embedding, Error>>
+    where
+        T: IntoIterator,
+        T::Item: Image,
+    {
+        self.encode_preprocessed(self.preprocess_images_each(images), normalize)
+    }
+
+    /// Encodes `images` in chunks of at most `max_batch_size` images, one row per image in order.
+    fn batch_encode(
+        &self,
+        mut images: Vec<clip_cpp_sys::clip_image_f32>,
+        normalize: bool,
+    ) -> Result<Embeddings, Error> {
+        let dim = self.vision_params.projection_dim() as usize;
+        let chunk = self.max_batch_size.unwrap_or(images.len());
+        let encode = encode_chunks(&mut images, dim, chunk, |images, out| {
+            self.encode_chunk(images, out, normalize)
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .concat();
+
+        let encode = Array2::from_shape_vec((images.len(), dim), encode).unwrap();
+        Ok(Embeddings::new(
+            encode,
+            normalize,
+            Modality::Image,
+            self.params(),
+        ))
+    }
 
+    /// Encodes `images` with one call into clip.cpp, writing one row per image to `encode`.
+    fn encode_chunk(
+        &self,
+        images: &mut [clip_cpp_sys::clip_image_f32],
+        encode: &mut [f32],
+        normalize: bool,
+    ) -> bool {
         let input_img_batch = clip_cpp_sys::clip_image_f32_batch {
             data: images.as_mut_ptr(),
             size: images.len(),
         };
-
         unsafe {
             clip_cpp_sys::clip_image_batch_encode(
                 self.ctx.as_ptr(),
@@ -261,14 +1034,59 @@ impl Model {
                 &input_img_batch,
                 encode.as_mut_ptr(),
                 normalize,
-            );
+            )
         }
+    }
 
-        encode
-            .chunks(self.vision_params.projection_dim() as usize)
-            .into_iter()
-            .map(|v| v.to_owned())
-            .collect()
+    #[deprecated(note = "use `try_encode_images` instead")]
+    pub fn encode_images<'a, T: IntoIterator<Item = &'a Blob>>(
+        &self,
+        images: T,
+        normalize: bool,
+    ) -> Vec<Vec<f32>> {
+        self.try_encode_images(images, normalize)
+            .map(|e| e.iter().map(Embedding::into_vec).collect())
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Classifies `image` against `labels` using the default prompt template, returning the labels
+    /// sorted by descending probability.
+    pub fn zero_shot_classify<I: Image, L: AsRef<str>>(
+        &self,
+        image: I,
+        labels: &[L],
+    ) -> Result<Vec<LabelScore>, Error> {
+        self.zero_shot_classify_with(image, labels, &ZeroShotOptions::default())
+    }
+
+    /// Classifies `image` against `labels` with prompts built from the templates in `options`,
+    /// returning the labels sorted by descending probability.
+    pub fn zero_shot_classify_with<I: Image, L: AsRef<str>>(
+        &self,
+        image: I,
+        labels: &[L],
+        options: &ZeroShotOptions,
+    ) -> Result<Vec<LabelScore>, Error> {
+        let classifier = ZeroShotClassifier::new(self, labels, options)?;
+        let preprocess = options.preprocess.as_ref();
+        let blob =
+            self.preprocess_image_with(image, preprocess.unwrap_or(&self.preprocess.options))?;
+        classifier.classify(&self.try_encode_image(&blob, true)?)
+    }
+
+    /// Decodes the image file at `path`, turns it upright according to its EXIF orientation,
+    /// then preprocesses and encodes it.
+    ///
+    /// Only JPEG decoding is enabled by default, other formats are available by enabling the
+    /// matching features of the `image` crate.
+    #[cfg(feature = "image")]
+    pub fn encode_image_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        normalize: bool,
+    ) -> Result<Embedding, Error> {
+        let image = super::image_buffer::open(path)?;
+        self.try_encode_image(&self.preprocess_image(&image)?, normalize)
     }
 }
 
@@ -279,3 +1097,140 @@ impl Drop for Model {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes each item as a row filled with its value, recording the chunk sizes.
+    fn encode_rows(items: &mut [f32], chunk: usize) -> (Result<Vec<f32>, Error>, Vec<usize>) {
+        let mut chunks = Vec::new();
+        let encoded = encode_chunks(items, 3, chunk, |items, out| {
+            chunks.push(items.len());
+            for (item, row) in items.iter().zip(out.chunks_exact_mut(3)) {
+                row.fill(*item);
+            }
+            true
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(|chunks| chunks.concat());
+        (encoded, chunks)
+    }
+
+    #[test]
+    fn chunked_encoding_keeps_rows_in_order() {
+        let mut items = (0..10).map(|i| i as f32).collect::<Vec<_>>();
+        let expected = items.iter().flat_map(|&i| [i; 3]).collect::<Vec<_>>();
+        for (chunk, sizes) in [
+            (4, vec![4, 4, 2]),
+            (10, vec![10]),
+            (64, vec![10]),
+            (0, vec![1; 10]),
+        ] {
+            let (encoded, chunks) = encode_rows(&mut items, chunk);
+            assert_eq!(encoded.unwrap(), expected, "chunk {chunk}");
+            assert_eq!(chunks, sizes, "chunk {chunk}");
+        }
+
+        let (encoded, chunks) = encode_rows(&mut [], 4);
+        assert!(encoded.unwrap().is_empty());
+        assert!(chunks.is_empty());
+    }
+
+    #[test]
+    fn chunked_encoding_fails_chunks_independently() {
+        let mut calls = 0;
+        let chunks = encode_chunks(&mut [0u8; 10], 3, 4, |items, out| {
+            calls += 1;
+            out.fill(calls as f32);
+            assert_eq!(out.len(), items.len() * 3);
+            calls != 2
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(calls, 3);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].as_ref().unwrap(), &[1.0; 12]);
+        assert!(matches!(chunks[1], Err(Error::ImageEncode)));
+        assert_eq!(chunks[2].as_ref().unwrap(), &[3.0; 6]);
+
+        // Only the rows of the failed chunk are errors.
+        for row in 0..10 {
+            let encoded = chunk_row(&chunks, 4, 3, row);
+            match row / 4 {
+                1 => assert!(matches!(encoded, Err(Error::ImageEncode)), "row {row}"),
+                n => assert_eq!(encoded.unwrap(), [n as f32 + 1.0; 3], "row {row}"),
+            }
+        }
+    }
+
+    #[test]
+    fn chunked_encoding_stops_at_first_failure_when_collected() {
+        let mut calls = 0;
+        let result = encode_chunks(&mut [0u8; 10], 3, 4, |_, _| {
+            calls += 1;
+            calls < 2
+        })
+        .collect::<Result<Vec<_>, _>>();
+        assert!(matches!(result, Err(Error::ImageEncode)));
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn build_rejects_invalid_normalization_before_loading() {
+        let builder = Model::builder("missing.gguf");
+        assert!(matches!(
+            builder.clone().image_std([0.5, 0.0, 0.5]).build(),
+            Err(Error::PreprocessConfig(_))
+        ));
+        assert!(matches!(
+            builder.clone().image_mean([f32::NAN; 3]).build(),
+            Err(Error::PreprocessConfig(_))
+        ));
+        assert!(matches!(
+            builder.image_mean([0.5; 3]).image_std([0.5; 3]).build(),
+            Err(Error::PathNotFound)
+        ));
+    }
+
+    #[test]
+    fn bytes_are_staged_once_for_all_clones() {
+        let bytes = b"GGUF, or so it claims".to_vec();
+        let builder = Model::builder_from_bytes(&bytes[..]).unwrap();
+        let clone = builder.clone().threads(4);
+        let (Source::Staged(file), Source::Staged(cloned)) = (&builder.source, &clone.source)
+        else {
+            panic!("bytes were not staged");
+        };
+        assert!(Arc::ptr_eq(file, cloned));
+        #[cfg(target_os = "linux")]
+        assert!(matches!(**file, StagedFile::Memfd { .. }));
+        assert_eq!(std::fs::read(file.path()).unwrap(), bytes);
+    }
+
+    #[test]
+    fn temp_files_skip_stale_names_and_are_removed_on_drop() {
+        let dir = std::env::temp_dir().join(format!("clip-staging-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("stale"), b"left by a crashed process").unwrap();
+
+        let names = ["stale", "fresh"].map(String::from);
+        let staged = StagedFile::temp(&dir, names.clone(), b"model").unwrap();
+        assert_eq!(staged.path(), dir.join("fresh"));
+        assert_eq!(std::fs::read(staged.path()).unwrap(), b"model");
+        assert_eq!(
+            std::fs::read(dir.join("stale")).unwrap(),
+            b"left by a crashed process"
+        );
+
+        // Every name is taken while `staged` is alive.
+        assert!(matches!(
+            StagedFile::temp(&dir, names.clone(), b"model"),
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::AlreadyExists
+        ));
+
+        drop(staged);
+        assert!(!dir.join("fresh").exists());
+        assert!(dir.join("stale").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
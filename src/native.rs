@@ -0,0 +1,73 @@
+use std::path::Path;
+use std::ptr::NonNull;
+
+use super::quantize::path_to_cstring;
+use super::{Error, Image};
+
+/// An RGB image decoded by clip.cpp's own (stb based) loader.
+///
+/// Together with [`Model::preprocess_native`](crate::Model::preprocess_native) this reproduces
+/// the exact model inputs of the upstream C++ tools. It also implements [`Image`], so the same
+/// pixels can be run through [`Model::preprocess_image`](crate::Model::preprocess_image) for
+/// comparison.
+#[derive(Debug)]
+pub struct NativeImage {
+    image: NonNull<clip_cpp_sys::clip_image_u8>,
+}
+
+unsafe impl Send for NativeImage {}
+unsafe impl Sync for NativeImage {}
+
+impl NativeImage {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path_to_cstring(path.as_ref())?;
+        let image = match NonNull::new(unsafe { clip_cpp_sys::clip_image_u8_make() }) {
+            Some(image) => Self { image },
+            None => return Err(Error::ImageLoad),
+        };
+        let ok =
+            unsafe { clip_cpp_sys::clip_image_load_from_file(path.as_ptr(), image.image.as_ptr()) };
+        if !ok {
+            return Err(Error::ImageLoad);
+        }
+        Ok(image)
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const clip_cpp_sys::clip_image_u8 {
+        self.image.as_ptr()
+    }
+
+    fn raw(&self) -> &clip_cpp_sys::clip_image_u8 {
+        unsafe { self.image.as_ref() }
+    }
+}
+
+impl Image for NativeImage {
+    fn width(&self) -> u32 {
+        self.raw().nx as u32
+    }
+
+    fn height(&self) -> u32 {
+        self.raw().ny as u32
+    }
+
+    fn size(&self) -> usize {
+        self.raw().size
+    }
+
+    fn data(&self) -> &[u8] {
+        let raw = self.raw();
+        if raw.data.is_null() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(raw.data, raw.size) }
+    }
+}
+
+impl Drop for NativeImage {
+    fn drop(&mut self) {
+        unsafe {
+            clip_cpp_sys::clip_image_u8_free(self.image.as_ptr());
+        }
+    }
+}
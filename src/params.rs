@@ -1,3 +1,4 @@
+#[derive(Debug, Clone, Copy)]
 pub struct TextParams {
     params: clip_cpp_sys::clip_text_hparams,
 }
@@ -36,12 +37,26 @@ impl TextParams {
     }
 }
 
+impl PartialEq for TextParams {
+    fn eq(&self, other: &Self) -> bool {
+        self.vocab() == other.vocab()
+            && self.positions() == other.positions()
+            && self.hidden_size() == other.hidden_size()
+            && self.intermediate() == other.intermediate()
+            && self.projection_dim() == other.projection_dim()
+            && self.head() == other.head()
+            && self.layer() == other.layer()
+            && self.eps() == other.eps()
+    }
+}
+
 impl From<clip_cpp_sys::clip_text_hparams> for TextParams {
     fn from(params: clip_cpp_sys::clip_text_hparams) -> Self {
         Self { params }
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct VisionParams {
     params: clip_cpp_sys::clip_vision_hparams,
 }
@@ -78,6 +93,58 @@ impl VisionParams {
     pub fn eps(&self) -> f32 {
         self.params.eps
     }
+
+    /// Rough estimate in bytes of the activations clip.cpp allocates to encode `batch_size` images
+    /// in one call: the input images plus the largest intermediates of a transformer layer.
+    ///
+    /// Each image is split into `(image_size / patch_size)^2` patches plus a class token. A ViT
+    /// layer holds, for every token, the query, key, value and attention output (`4 * hidden_size`
+    /// floats) and the MLP expansion (`intermediate` floats), and for every head the attention
+    /// scores and their softmax (`2 * tokens^2` floats). Layers run one after the other, so only
+    /// one layer is counted, and the weights are not included. This is an estimate rather than
+    /// a measurement, leave some headroom.
+    pub fn batch_memory(&self, batch_size: usize) -> usize {
+        let dim = |value: i32| value.max(0) as usize;
+        let size = dim(self.image_size());
+        let patches = size.checked_div(dim(self.patch_size())).unwrap_or(0);
+        let tokens = patches.saturating_mul(patches).saturating_add(1);
+        let hidden = dim(self.hidden_size());
+        let intermediate = dim(self.intermediate());
+        let heads = dim(self.head());
+
+        let input = size.saturating_mul(size).saturating_mul(3);
+        let layer = tokens.saturating_mul(hidden.saturating_mul(4).saturating_add(intermediate));
+        let attention = tokens
+            .saturating_mul(tokens)
+            .saturating_mul(heads)
+            .saturating_mul(2);
+        let per_image = input.saturating_add(layer).saturating_add(attention);
+        per_image
+            .saturating_mul(batch_size)
+            .saturating_mul(std::mem::size_of::<f32>())
+    }
+
+    /// Largest batch size whose [`batch_memory`](Self::batch_memory) fits in `bytes`, at least
+    /// one.
+    pub fn batch_size_for_memory(&self, bytes: usize) -> usize {
+        bytes
+            .checked_div(self.batch_memory(1))
+            .unwrap_or(usize::MAX)
+            .max(1)
+    }
+}
+
+impl PartialEq for VisionParams {
+    fn eq(&self, other: &Self) -> bool {
+        self.image_size() == other.image_size()
+            && self.patch_size() == other.patch_size()
+            && self.hidden_size() == other.hidden_size()
+            && self.intermediate() == other.intermediate()
+            && self.projection_dim() == other.projection_dim()
+            && self.head() == other.head()
+            && self.layer() == other.layer()
+            && self.eps() == other.eps()
+    }
 }
 
 impl From<clip_cpp_sys::clip_vision_hparams> for VisionParams {
@@ -85,3 +152,108 @@ impl From<clip_cpp_sys::clip_vision_hparams> for VisionParams {
         Self { params }
     }
 }
+
+/// Hyperparameters of both encoders, identifying the model an embedding was produced by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ModelParams {
+    pub(crate) text: TextParams,
+    pub(crate) vision: VisionParams,
+}
+
+#[cfg(test)]
+impl ModelParams {
+    /// ViT-B/32 hyperparameters with the given projection dimension and image size.
+    pub(crate) fn test(projection_dim: i32, image_size: i32) -> Self {
+        let text = clip_cpp_sys::clip_text_hparams {
+            n_vocab: 49408,
+            num_positions: 77,
+            hidden_size: 512,
+            n_intermediate: 2048,
+            projection_dim,
+            n_head: 8,
+            n_layer: 12,
+            eps: 1e-5,
+        };
+        let vision = clip_cpp_sys::clip_vision_hparams {
+            image_size,
+            patch_size: 32,
+            hidden_size: 768,
+            n_intermediate: 3072,
+            projection_dim,
+            n_head: 12,
+            n_layer: 12,
+            eps: 1e-5,
+        };
+        Self {
+            text: text.into(),
+            vision: vision.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_memory_of_vit_b_32() {
+        let vision = ModelParams::test(512, 224).vision;
+        // 7x7 patches and the class token.
+        let tokens = 50;
+        let per_image = 3 * 224 * 224 + tokens * (4 * 768 + 3072) + 2 * 12 * tokens * tokens;
+        assert_eq!(vision.batch_memory(1), per_image * 4);
+        assert_eq!(vision.batch_memory(16), per_image * 4 * 16);
+        assert_eq!(vision.batch_memory(0), 0);
+    }
+
+    #[test]
+    fn batch_size_for_memory_rounds_down_to_at_least_one() {
+        let vision = ModelParams::test(512, 224).vision;
+        let per_image = vision.batch_memory(1);
+        assert_eq!(vision.batch_size_for_memory(0), 1);
+        assert_eq!(vision.batch_size_for_memory(per_image - 1), 1);
+        assert_eq!(vision.batch_size_for_memory(per_image * 8), 8);
+        assert_eq!(vision.batch_size_for_memory(per_image * 9 - 1), 8);
+    }
+
+    #[test]
+    fn batch_memory_handles_degenerate_params() {
+        let mut params = ModelParams::test(512, 224).vision.params;
+        params.patch_size = 0;
+        let vision = VisionParams::from(params);
+        // Without patches only the input image and the class token are counted.
+        let per_image = 3 * 224 * 224 + (4 * 768 + 3072) + 2 * 12;
+        assert_eq!(vision.batch_memory(1), per_image * 4);
+        assert_eq!(vision.batch_memory(usize::MAX), usize::MAX);
+
+        params.image_size = 0;
+        params.hidden_size = 0;
+        params.n_intermediate = 0;
+        params.n_head = 0;
+        let empty = VisionParams::from(params);
+        assert_eq!(empty.batch_memory(1), 0);
+        assert_eq!(empty.batch_size_for_memory(1024), usize::MAX);
+    }
+
+    #[test]
+    fn batch_memory_saturates_on_huge_params() {
+        let mut params = ModelParams::test(512, 224).vision.params;
+        params.image_size = i32::MAX;
+        params.patch_size = 1;
+        let vision = VisionParams::from(params);
+        assert_eq!(vision.batch_memory(1), usize::MAX);
+        assert_eq!(vision.batch_size_for_memory(usize::MAX), 1);
+
+        // Large layers without overflowing.
+        params.image_size = 224;
+        params.patch_size = 32;
+        params.hidden_size = i32::MAX;
+        params.n_intermediate = i32::MAX;
+        params.n_head = i32::MAX;
+        let vision = VisionParams::from(params);
+        let max = i32::MAX as u128;
+        let per_image = 3 * 224 * 224 + 50 * 5 * max + 2 * max * 50 * 50;
+        let expected = usize::try_from(per_image * 4).unwrap_or(usize::MAX);
+        assert_eq!(vision.batch_memory(1), expected);
+    }
+}
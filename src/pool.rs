@@ -0,0 +1,299 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+use super::{Blob, Embedding, Embeddings, Error, Image, Model, ModelBuilder};
+
+/// Idle items of a pool, handed out to one checkout at a time.
+#[derive(Debug)]
+struct Slots<T> {
+    idle: Mutex<Vec<T>>,
+    available: Condvar,
+}
+
+impl<T> Default for Slots<T> {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl<T> Slots<T> {
+    fn new(items: Vec<T>) -> Self {
+        Self {
+            idle: Mutex::new(items),
+            available: Condvar::new(),
+        }
+    }
+
+    fn idle(&self) -> MutexGuard<'_, Vec<T>> {
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Takes an idle item, waiting for one to be returned if there is none.
+    fn take(&self) -> T {
+        let mut idle = self.idle();
+        loop {
+            if let Some(item) = idle.pop() {
+                return item;
+            }
+            idle = self
+                .available
+                .wait(idle)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn try_take(&self) -> Option<T> {
+        self.idle().pop()
+    }
+
+    /// Returns an item, waking one waiting [`take`](Self::take).
+    fn put(&self, item: T) {
+        self.idle().push(item);
+        self.available.notify_one();
+    }
+}
+
+/// A fixed set of model contexts shared between threads.
+///
+/// Each context loads its own copy of the weights, clip.cpp has no way to share them between
+/// contexts. Checkouts block until a context is idle and return it to the pool when dropped.
+pub struct ModelPool {
+    models: Slots<Model>,
+    size: usize,
+}
+
+impl ModelPool {
+    /// Loads `size` contexts with the settings of `builder`.
+    pub fn new(builder: ModelBuilder, size: usize) -> Result<Self, Error> {
+        if size == 0 {
+            return Err(Error::EmptyPool);
+        }
+        let models = (0..size)
+            .map(|_| builder.clone().build())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            models: Slots::new(models),
+            size,
+        })
+    }
+
+    /// Number of contexts in the pool.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Checks out an idle context, waiting for one to be returned if all are in use.
+    pub fn get(&self) -> PooledModel<'_> {
+        PooledModel {
+            pool: self,
+            model: Some(self.models.take()),
+        }
+    }
+
+    /// Checks out an idle context if there is one.
+    pub fn try_get(&self) -> Option<PooledModel<'_>> {
+        self.models.try_take().map(|model| PooledModel {
+            pool: self,
+            model: Some(model),
+        })
+    }
+
+    pub fn encode_text<T: AsRef<str>>(&self, text: T, normalize: bool) -> Result<Embedding, Error> {
+        self.get().encode_text(text, normalize)
+    }
+
+    pub fn encode_texts<T>(&self, texts: T, normalize: bool) -> Result<Embeddings, Error>
+    where
+        T: IntoIterator,
+        T::Item: AsRef<str>,
+    {
+        self.get().encode_texts(texts, normalize)
+    }
+
+    /// Preprocesses `image` with the model's preprocessing and encodes it.
+    pub fn encode_image<I: Image>(&self, image: I, normalize: bool) -> Result<Embedding, Error> {
+        let model = self.get();
+        let blob = model.preprocess_image(image)?;
+        model.try_encode_image(&blob, normalize)
+    }
+
+    /// Preprocesses `images` with the model's preprocessing and encodes them as one batch.
+    pub fn encode_images<T>(&self, images: T, normalize: bool) -> Result<Embeddings, Error>
+    where
+        T: IntoIterator,
+        T::Item: Image,
+    {
+        let model = self.get();
+        let blobs = model.preprocess_images(images)?;
+        model.try_encode_images(&blobs, normalize)
+    }
+
+    /// Preprocesses `images` with the model's preprocessing and encodes the ones that succeed
+    /// as one batch, returning one result per image in input order.
+    pub fn encode_images_each<T>(&self, images: T, normalize: bool) -> Vec<Result<Embedding, Error>>
+    where
+        T: IntoIterator,
+        T::Item: Image,
+    {
+        self.get().encode_images_each(images, normalize)
+    }
+}
+
+/// A context checked out of a [`ModelPool`], returned to the pool on drop.
+pub struct PooledModel<'a> {
+    pool: &'a ModelPool,
+    model: Option<Model>,
+}
+
+impl Deref for PooledModel<'_> {
+    type Target = Model;
+
+    fn deref(&self) -> &Model {
+        self.model.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledModel<'_> {
+    fn drop(&mut self) {
+        if let Some(model) = self.model.take() {
+            self.pool.models.put(model);
+        }
+    }
+}
+
+/// Preprocessing buffers recycled between images instead of allocating a [`Blob`] per image.
+#[derive(Debug, Default)]
+pub struct BlobPool {
+    blobs: Slots<Blob>,
+}
+
+impl BlobPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks out an idle buffer, allocating a new one if all are in use.
+    pub fn get(&self) -> PooledBlob<'_> {
+        PooledBlob {
+            pool: self,
+            blob: Some(self.blobs.try_take().unwrap_or_default()),
+        }
+    }
+
+    /// Checks out a buffer and preprocesses `image` into it with the model's preprocessing.
+    pub fn preprocess<I: Image>(&self, model: &Model, image: I) -> Result<PooledBlob<'_>, Error> {
+        let mut blob = self.get();
+        model.preprocess_into(image, &mut blob)?;
+        Ok(blob)
+    }
+}
+
+/// A buffer checked out of a [`BlobPool`], returned to the pool on drop.
+pub struct PooledBlob<'a> {
+    pool: &'a BlobPool,
+    blob: Option<Blob>,
+}
+
+impl Deref for PooledBlob<'_> {
+    type Target = Blob;
+
+    fn deref(&self) -> &Blob {
+        self.blob.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledBlob<'_> {
+    fn deref_mut(&mut self) -> &mut Blob {
+        self.blob.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledBlob<'_> {
+    fn drop(&mut self) {
+        if let Some(blob) = self.blob.take() {
+            self.pool.blobs.put(blob);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{PreprocessConfig, PreprocessOptions, RGBImageView};
+
+    #[test]
+    fn slots_hand_out_each_item_once() {
+        let slots = Slots::new(vec![1, 2]);
+        let (a, b) = (slots.take(), slots.take());
+        assert_eq!([a, b], [2, 1]);
+        assert_eq!(slots.try_take(), None);
+
+        slots.put(a);
+        assert_eq!(slots.try_take(), Some(a));
+        assert_eq!(slots.try_take(), None);
+    }
+
+    #[test]
+    fn take_waits_for_an_item_to_be_returned() {
+        let slots = Slots::new(vec![7]);
+        let item = slots.take();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::scope(|scope| {
+            scope.spawn(|| sender.send(slots.take()).unwrap());
+            assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+            slots.put(item);
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok(7));
+        });
+    }
+
+    fn config(size: i32) -> PreprocessConfig {
+        PreprocessConfig::new(size, [0.0; 3], [1.0; 3], PreprocessOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn blobs_are_returned_and_reused() {
+        let pool = BlobPool::new();
+        let pixels = [128u8; 6 * 4 * 3];
+        let image = RGBImageView::new(6, 4, &pixels).unwrap();
+
+        let mut blob = pool.get();
+        config(8)
+            .preprocess_into_with(image, &PreprocessOptions::default(), &mut blob)
+            .unwrap();
+        let data = blob.as_slice().as_ptr();
+        drop(blob);
+        assert_eq!(pool.blobs.idle().len(), 1);
+
+        // The returned buffer is handed out again, a second checkout allocates a new one.
+        let first = pool.get();
+        let second = pool.get();
+        assert_eq!(first.as_slice().as_ptr(), data);
+        assert_eq!(first.as_slice().len(), 8 * 8 * 3);
+        assert!(second.as_slice().is_empty());
+        assert!(pool.blobs.idle().is_empty());
+        drop((first, second));
+        assert_eq!(pool.blobs.idle().len(), 2);
+    }
+
+    #[test]
+    fn reused_blobs_follow_the_input_size() {
+        let pool = BlobPool::new();
+        let pixels = [64u8; 6 * 4 * 3];
+        let image = RGBImageView::new(6, 4, &pixels).unwrap();
+        let options = PreprocessOptions::default();
+
+        for size in [8, 4, 16, 8] {
+            let mut blob = pool.get();
+            config(size)
+                .preprocess_into_with(image, &options, &mut blob)
+                .unwrap();
+            let expected = config(size).preprocess_image(image).unwrap();
+            assert_eq!(blob.as_slice(), expected.as_slice(), "size {size}");
+        }
+        assert_eq!(pool.blobs.idle().len(), 1);
+    }
+}
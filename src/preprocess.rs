@@ -0,0 +1,929 @@
+use std::ops::Range;
+
+use super::{Blob, Error, Image, PixelFormat};
+
+/// How an image is fitted into the square model input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizeMode {
+    /// Resize the shortest side to the input size and crop the center, as the reference CLIP
+    /// pipeline does.
+    #[default]
+    CenterCrop,
+    /// Resize both sides to the input size, ignoring the aspect ratio.
+    Squash,
+    /// Resize the longest side to the input size and pad the remaining area.
+    Letterbox,
+}
+
+/// Resampling filter used when resizing, matching the PIL filters of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterType {
+    Nearest,
+    Bilinear,
+    #[default]
+    Bicubic,
+}
+
+impl FilterType {
+    fn support(self) -> f32 {
+        match self {
+            Self::Nearest => 0.5,
+            Self::Bilinear => 1.0,
+            Self::Bicubic => 2.0,
+        }
+    }
+
+    fn weight(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            Self::Nearest => unreachable!(),
+            Self::Bilinear => {
+                if x < 1.0 {
+                    1.0 - x
+                } else {
+                    0.0
+                }
+            }
+            Self::Bicubic => {
+                const A: f32 = -0.5;
+                if x < 1.0 {
+                    ((A + 2.0) * x - (A + 3.0)) * x * x + 1.0
+                } else if x < 2.0 {
+                    (((x - 5.0) * x + 8.0) * x - 4.0) * A
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Options controlling how [`Model::preprocess_image_with`](crate::Model::preprocess_image_with)
+/// turns an image into a model input.
+///
+/// The default is the reference CLIP pipeline: a bicubic resize of the shortest side followed by a
+/// center crop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreprocessOptions {
+    mode: ResizeMode,
+    filter: FilterType,
+    pad_color: [u8; 3],
+    background: [u8; 3],
+}
+
+impl Default for PreprocessOptions {
+    fn default() -> Self {
+        Self {
+            mode: ResizeMode::default(),
+            filter: FilterType::default(),
+            pad_color: [0, 0, 0],
+            background: [255, 255, 255],
+        }
+    }
+}
+
+impl PreprocessOptions {
+    pub fn with_mode(mut self, mode: ResizeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: FilterType) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Color of the padding added by [`ResizeMode::Letterbox`], black by default.
+    pub fn with_pad_color(mut self, pad_color: [u8; 3]) -> Self {
+        self.pad_color = pad_color;
+        self
+    }
+
+    /// Color translucent pixels are composited onto, white by default.
+    pub fn with_background(mut self, background: [u8; 3]) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn mode(&self) -> ResizeMode {
+        self.mode
+    }
+
+    pub fn filter(&self) -> FilterType {
+        self.filter
+    }
+
+    pub fn pad_color(&self) -> [u8; 3] {
+        self.pad_color
+    }
+
+    pub fn background(&self) -> [u8; 3] {
+        self.background
+    }
+}
+
+/// Fails with [`Error::PreprocessConfig`] unless every channel of `mean` is finite.
+pub(crate) fn check_mean(mean: &[f32; 3]) -> Result<(), Error> {
+    if !mean.iter().all(|m| m.is_finite()) {
+        return Err(Error::PreprocessConfig("image mean must be finite"));
+    }
+    Ok(())
+}
+
+/// Fails with [`Error::PreprocessConfig`] unless every channel of `std` is finite and non-zero,
+/// normalized values would be infinite or NaN otherwise.
+pub(crate) fn check_std(std: &[f32; 3]) -> Result<(), Error> {
+    if !std.iter().all(|s| s.is_finite() && *s != 0.0) {
+        return Err(Error::PreprocessConfig(
+            "image std must be finite and non-zero",
+        ));
+    }
+    Ok(())
+}
+
+/// Preprocessing a [`Model`](crate::Model) applies to images unless given other options.
+///
+/// A config can also be built directly to preprocess images without loading a model, the
+/// resulting blobs can be encoded by any model with the same input size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreprocessConfig {
+    pub(crate) image_size: i32,
+    pub(crate) mean: [f32; 3],
+    pub(crate) std: [f32; 3],
+    pub(crate) options: PreprocessOptions,
+}
+
+impl PreprocessConfig {
+    /// Preprocessing into a `image_size x image_size` input normalized with the per-channel `mean`
+    /// and `std`.
+    ///
+    /// Fails with [`Error::PreprocessConfig`] unless `image_size` is positive, `mean` is finite and
+    /// `std` is finite and non-zero.
+    pub fn new(
+        image_size: i32,
+        mean: [f32; 3],
+        std: [f32; 3],
+        options: PreprocessOptions,
+    ) -> Result<Self, Error> {
+        if image_size <= 0 {
+            return Err(Error::PreprocessConfig("image size must be positive"));
+        }
+        check_mean(&mean)?;
+        check_std(&std)?;
+        Ok(Self {
+            image_size,
+            mean,
+            std,
+            options,
+        })
+    }
+
+    /// Side of the square model input.
+    pub fn image_size(&self) -> i32 {
+        self.image_size
+    }
+
+    /// Per-channel mean subtracted from RGB values in `0.0..=1.0`.
+    pub fn mean(&self) -> [f32; 3] {
+        self.mean
+    }
+
+    /// Per-channel standard deviation RGB values are divided by after subtracting the mean.
+    pub fn std(&self) -> [f32; 3] {
+        self.std
+    }
+
+    pub fn options(&self) -> &PreprocessOptions {
+        &self.options
+    }
+
+    /// Resizes, crops and normalizes `image` with this config's options.
+    pub fn preprocess_image<I: Image>(&self, image: I) -> Result<Blob, Error> {
+        self.preprocess_image_with(image, &self.options)
+    }
+
+    /// Resizes and normalizes `image` into the input size as described by `options`.
+    pub fn preprocess_image_with<I: Image>(
+        &self,
+        image: I,
+        options: &PreprocessOptions,
+    ) -> Result<Blob, Error> {
+        let mut blob = Blob::default();
+        self.preprocess_into_with(image, options, &mut blob)?;
+        Ok(blob)
+    }
+
+    /// Same as [`preprocess_image_with`](Self::preprocess_image_with), reusing the allocation of
+    /// `blob`.
+    pub fn preprocess_into_with<I: Image>(
+        &self,
+        image: I,
+        options: &PreprocessOptions,
+        blob: &mut Blob,
+    ) -> Result<(), Error> {
+        let dest = blob.reset(self.image_size);
+        self.write(&image, options, dest)
+    }
+
+    /// Converts `data`, interleaved RGB inputs normalized with `mean` and `std`, to this config's
+    /// normalization.
+    pub(crate) fn renormalize(&self, data: &mut [f32], mean: [f32; 3], std: [f32; 3]) {
+        if (mean, std) == (self.mean, self.std) {
+            return;
+        }
+        for (i, v) in data.iter_mut().enumerate() {
+            let c = i % 3;
+            *v = (*v * std[c] + mean[c] - self.mean[c]) / self.std[c];
+        }
+    }
+
+    /// Preprocesses `image` into `dest`, which holds exactly one input.
+    pub(crate) fn write<I: Image>(
+        &self,
+        image: &I,
+        options: &PreprocessOptions,
+        dest: &mut [f32],
+    ) -> Result<(), Error> {
+        preprocess(
+            image,
+            self.image_size as usize,
+            &self.mean,
+            &self.std,
+            options,
+            dest,
+        )
+    }
+}
+
+/// Resampling taps contributing to a single output pixel.
+struct Taps {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+/// Computes the taps for the output pixels in `range` when resampling `in_size` pixels to
+/// `out_size`, following PIL's antialiased resampling.
+fn taps(filter: FilterType, in_size: usize, out_size: usize, range: Range<usize>) -> Vec<Taps> {
+    let scale = in_size as f32 / out_size as f32;
+    if filter == FilterType::Nearest {
+        return range
+            .map(|o| Taps {
+                start: (((o as f32 + 0.5) * scale) as usize).min(in_size - 1),
+                weights: vec![1.0],
+            })
+            .collect();
+    }
+
+    let filter_scale = scale.max(1.0);
+    let support = filter.support() * filter_scale;
+    range
+        .map(|o| {
+            let center = (o as f32 + 0.5) * scale;
+            let start = (center - support + 0.5).max(0.0) as usize;
+            let end = ((center + support + 0.5) as usize).min(in_size);
+            let mut weights = (start..end)
+                .map(|i| filter.weight((i as f32 - center + 0.5) / filter_scale))
+                .collect::<Vec<_>>();
+            let total: f32 = weights.iter().sum();
+            if total != 0.0 {
+                weights.iter_mut().for_each(|w| *w /= total);
+            }
+            Taps { start, weights }
+        })
+        .collect()
+}
+
+/// Rounds a resampled value back onto the 8-bit grid, as PIL does between passes.
+fn quantize(value: f32) -> f32 {
+    value.round().clamp(0.0, 255.0)
+}
+
+/// Reads the `i`th native-endian `u16` sample of a pixel, scaled to `0.0..=255.0`.
+fn read_u16(p: &[u8], i: usize) -> f32 {
+    u16::from_ne_bytes([p[i * 2], p[i * 2 + 1]]) as f32 / 257.0
+}
+
+/// Reads the `i`th native-endian `f32` sample of a pixel, scaled to `0.0..=255.0`.
+fn read_f32(p: &[u8], i: usize) -> f32 {
+    let bytes = [p[i * 4], p[i * 4 + 1], p[i * 4 + 2], p[i * 4 + 3]];
+    (f32::from_ne_bytes(bytes) * 255.0).clamp(0.0, 255.0)
+}
+
+/// Reads a pixel as RGBA values in `0.0..=255.0`.
+fn pixel_reader(format: PixelFormat) -> fn(&[u8]) -> [f32; 4] {
+    match format {
+        PixelFormat::Rgb8 => |p| [p[0] as f32, p[1] as f32, p[2] as f32, 255.0],
+        PixelFormat::Rgba8 => |p| [p[0] as f32, p[1] as f32, p[2] as f32, p[3] as f32],
+        PixelFormat::Bgr8 => |p| [p[2] as f32, p[1] as f32, p[0] as f32, 255.0],
+        PixelFormat::Bgra8 => |p| [p[2] as f32, p[1] as f32, p[0] as f32, p[3] as f32],
+        PixelFormat::Gray8 => |p| [p[0] as f32, p[0] as f32, p[0] as f32, 255.0],
+        PixelFormat::GrayA8 => |p| [p[0] as f32, p[0] as f32, p[0] as f32, p[1] as f32],
+        PixelFormat::Gray16 => |p| {
+            let g = read_u16(p, 0);
+            [g, g, g, 255.0]
+        },
+        PixelFormat::GrayA16 => |p| {
+            let g = read_u16(p, 0);
+            [g, g, g, read_u16(p, 1)]
+        },
+        PixelFormat::Rgb16 => |p| [read_u16(p, 0), read_u16(p, 1), read_u16(p, 2), 255.0],
+        PixelFormat::Rgba16 => |p| std::array::from_fn(|i| read_u16(p, i)),
+        PixelFormat::Rgb32F => |p| [read_f32(p, 0), read_f32(p, 1), read_f32(p, 2), 255.0],
+        PixelFormat::Rgba32F => |p| std::array::from_fn(|i| read_f32(p, i)),
+        PixelFormat::Nv12 | PixelFormat::I420 => unreachable!("planar formats are read by rows"),
+    }
+}
+
+/// Converts BT.601 limited range YUV to RGB values in `0.0..=255.0`.
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [f32; 3] {
+    let y = 1.164 * (y as f32 - 16.0);
+    let u = u as f32 - 128.0;
+    let v = v as f32 - 128.0;
+    [
+        (y + 1.596 * v).clamp(0.0, 255.0),
+        (y - 0.392 * u - 0.813 * v).clamp(0.0, 255.0),
+        (y + 2.017 * u).clamp(0.0, 255.0),
+    ]
+}
+
+/// Reads row `y` of a YUV 4:2:0 image into `out` as packed RGB values.
+fn load_yuv_row<I: Image>(image: &I, y: usize, out: &mut [f32]) {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let stride = image.stride();
+    let data = image.data();
+    let luma = &data[y * stride..][..width];
+    let chroma = &data[stride * height..];
+
+    // Both formats store one U and one V sample per 2x2 block, `step` bytes apart within a row.
+    let (u_row, v_row, step) = match image.format() {
+        PixelFormat::Nv12 => {
+            let row = &chroma[(y / 2) * stride..];
+            (row, &row[1..], 2)
+        }
+        _ => {
+            let chroma_stride = stride.div_ceil(2);
+            let plane = chroma_stride * height.div_ceil(2);
+            (
+                &chroma[(y / 2) * chroma_stride..],
+                &chroma[plane + (y / 2) * chroma_stride..],
+                1,
+            )
+        }
+    };
+    for (x, (o, &l)) in out.chunks_exact_mut(3).zip(luma).enumerate() {
+        o.copy_from_slice(&yuv_to_rgb(l, u_row[x / 2 * step], v_row[x / 2 * step]));
+    }
+}
+
+/// Reads row `y` of `image` into `out` as packed RGB values in `0.0..=255.0`, compositing
+/// translucent pixels onto `background`.
+fn load_row<I: Image>(image: &I, y: usize, background: [u8; 3], out: &mut [f32]) {
+    let format = image.format();
+    if let PixelFormat::Nv12 | PixelFormat::I420 = format {
+        return load_yuv_row(image, y, out);
+    }
+    let row_len = image.width() as usize * format.bytes_per_pixel();
+    let row = &image.data()[y * image.stride()..][..row_len];
+    let read = pixel_reader(format);
+    for (o, p) in out
+        .chunks_exact_mut(3)
+        .zip(row.chunks_exact(format.bytes_per_pixel()))
+    {
+        let [r, g, b, a] = read(p);
+        let a = a / 255.0;
+        for ((o, c), bg) in o.iter_mut().zip([r, g, b]).zip(background) {
+            *o = c * a + bg as f32 * (1.0 - a);
+        }
+    }
+}
+
+/// Placement of the resized image inside the square model input.
+struct Layout {
+    /// Size of the whole image after resizing.
+    resized: (usize, usize),
+    /// Region of the resized image that is kept.
+    crop: (Range<usize>, Range<usize>),
+    /// Offset of the kept region inside the model input.
+    offset: (usize, usize),
+}
+
+impl Layout {
+    fn new(mode: ResizeMode, width: usize, height: usize, size: usize) -> Self {
+        match mode {
+            ResizeMode::Squash => Self {
+                resized: (size, size),
+                crop: (0..size, 0..size),
+                offset: (0, 0),
+            },
+            ResizeMode::CenterCrop => {
+                // torchvision truncates the long side and rounds the crop offset half to even.
+                let (rw, rh) = if width <= height {
+                    (size, (size * height / width).max(size))
+                } else {
+                    ((size * width / height).max(size), size)
+                };
+                let left = ((rw - size) as f32 / 2.0).round_ties_even() as usize;
+                let top = ((rh - size) as f32 / 2.0).round_ties_even() as usize;
+                Self {
+                    resized: (rw, rh),
+                    crop: (left..left + size, top..top + size),
+                    offset: (0, 0),
+                }
+            }
+            ResizeMode::Letterbox => {
+                let long = width.max(height) as f32;
+                let rw = ((width as f32 * size as f32 / long).round() as usize).clamp(1, size);
+                let rh = ((height as f32 * size as f32 / long).round() as usize).clamp(1, size);
+                Self {
+                    resized: (rw, rh),
+                    crop: (0..rw, 0..rh),
+                    offset: ((size - rw) / 2, (size - rh) / 2),
+                }
+            }
+        }
+    }
+}
+
+/// Resizes `image` into a `size x size` input according to `options` and normalizes it with the
+/// per-channel `mean` and `std`, writing the packed RGB result into `dest`.
+pub(crate) fn preprocess<I: Image>(
+    image: &I,
+    size: usize,
+    mean: &[f32],
+    std: &[f32],
+    options: &PreprocessOptions,
+    dest: &mut [f32],
+) -> Result<(), Error> {
+    assert_eq!(dest.len(), size * size * 3);
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let format = image.format();
+    let stride = image.stride();
+    if width == 0
+        || height == 0
+        || stride < format.min_stride(width)
+        || image.data().len() < format.min_len(width, height, stride)
+    {
+        return Err(Error::Preprocess);
+    }
+
+    let layout = Layout::new(options.mode, width, height, size);
+    let (cols, rows) = layout.crop.clone();
+    let out_cols = cols.len();
+    let col_taps = taps(options.filter, width, layout.resized.0, cols);
+    let row_taps = taps(options.filter, height, layout.resized.1, rows);
+
+    // Only the source rows reached by the vertical taps need to be resampled horizontally.
+    let first_row = row_taps.iter().map(|t| t.start).min().unwrap_or(0);
+    let last_row = row_taps
+        .iter()
+        .map(|t| t.start + t.weights.len())
+        .max()
+        .unwrap_or(0);
+
+    let mut row = vec![0f32; width * 3];
+    let mut horizontal = vec![0f32; (last_row - first_row) * out_cols * 3];
+    for (y, out) in (first_row..last_row).zip(horizontal.chunks_exact_mut(out_cols * 3)) {
+        load_row(image, y, options.background, &mut row);
+        for (taps, px) in col_taps.iter().zip(out.chunks_exact_mut(3)) {
+            let mut acc = [0f32; 3];
+            for (i, w) in taps.weights.iter().enumerate() {
+                let src = &row[(taps.start + i) * 3..][..3];
+                acc.iter_mut().zip(src).for_each(|(a, s)| *a += w * s);
+            }
+            px.iter_mut().zip(acc).for_each(|(p, a)| *p = quantize(a));
+        }
+    }
+
+    let normalize = |c: usize, v: f32| (v / 255.0 - mean[c]) / std[c];
+    let pad: [f32; 3] = std::array::from_fn(|c| normalize(c, options.pad_color[c] as f32));
+    dest.chunks_exact_mut(3)
+        .for_each(|px| px.copy_from_slice(&pad));
+
+    let (dx, dy) = layout.offset;
+    for (y, taps) in row_taps.iter().enumerate() {
+        let out = &mut dest[((dy + y) * size + dx) * 3..][..out_cols * 3];
+        for (x, px) in out.chunks_exact_mut(3).enumerate() {
+            let mut acc = [0f32; 3];
+            for (i, w) in taps.weights.iter().enumerate() {
+                let src = &horizontal[((taps.start + i - first_row) * out_cols + x) * 3..][..3];
+                acc.iter_mut().zip(src).for_each(|(a, s)| *a += w * s);
+            }
+            for (c, (p, a)) in px.iter_mut().zip(acc).enumerate() {
+                *p = normalize(c, quantize(a));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{I420Image, NV12Image, RGBImageView};
+
+    const SIZE: usize = 8;
+
+    /// A tightly packed image in any format.
+    struct Raw {
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        data: Vec<u8>,
+    }
+
+    impl Image for Raw {
+        fn width(&self) -> u32 {
+            self.width
+        }
+
+        fn height(&self) -> u32 {
+            self.height
+        }
+
+        fn size(&self) -> usize {
+            self.data.len()
+        }
+
+        fn data(&self) -> &[u8] {
+            &self.data
+        }
+
+        fn format(&self) -> PixelFormat {
+            self.format
+        }
+    }
+
+    /// A 13x9 test pattern, every channel varying differently.
+    fn pattern() -> Vec<[u8; 3]> {
+        (0..9)
+            .flat_map(|y| {
+                (0..13).map(move |x| {
+                    [
+                        (x * 37 + y * 11) as u8,
+                        (x * 5 + y * 29) as u8,
+                        (x * y * 13 + 7) as u8,
+                    ]
+                })
+            })
+            .collect()
+    }
+
+    fn image<F: Fn([u8; 3]) -> Vec<u8>>(format: PixelFormat, pixel: F) -> Raw {
+        Raw {
+            width: 13,
+            height: 9,
+            format,
+            data: pattern().into_iter().flat_map(pixel).collect(),
+        }
+    }
+
+    /// Preprocesses with an identity normalization, so the output is RGB scaled to `0.0..=1.0`.
+    fn run<I: Image>(image: &I, options: &PreprocessOptions) -> Vec<f32> {
+        let mut dest = vec![0.0; SIZE * SIZE * 3];
+        preprocess(image, SIZE, &[0.0; 3], &[1.0; 3], options, &mut dest).unwrap();
+        dest
+    }
+
+    fn all_options() -> Vec<PreprocessOptions> {
+        let modes = [
+            ResizeMode::CenterCrop,
+            ResizeMode::Squash,
+            ResizeMode::Letterbox,
+        ];
+        let filters = [
+            FilterType::Nearest,
+            FilterType::Bilinear,
+            FilterType::Bicubic,
+        ];
+        modes
+            .into_iter()
+            .flat_map(|mode| {
+                filters.into_iter().map(move |filter| {
+                    PreprocessOptions::default()
+                        .with_mode(mode)
+                        .with_filter(filter)
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bgr_matches_rgb_with_swapped_channels() {
+        let rgb = image(PixelFormat::Rgb8, |[r, g, b]| vec![r, g, b]);
+        let bgr = image(PixelFormat::Bgr8, |[r, g, b]| vec![b, g, r]);
+        let bgra = image(PixelFormat::Bgra8, |[r, g, b]| vec![b, g, r, 255]);
+        for options in all_options() {
+            let expected = run(&rgb, &options);
+            assert_eq!(run(&bgr, &options), expected, "{options:?}");
+            assert_eq!(run(&bgra, &options), expected, "{options:?}");
+        }
+    }
+
+    #[test]
+    fn alpha_is_composited_onto_background() {
+        let options = PreprocessOptions::default().with_background([10, 200, 30]);
+        let rgb = image(PixelFormat::Rgb8, |[r, g, b]| vec![r, g, b]);
+        let opaque = image(PixelFormat::Rgba8, |[r, g, b]| vec![r, g, b, 255]);
+        assert_eq!(run(&opaque, &options), run(&rgb, &options));
+
+        let expected = [10.0 / 255.0, 200.0 / 255.0, 30.0 / 255.0].repeat(SIZE * SIZE);
+        let transparent = [
+            image(PixelFormat::Rgba8, |[r, g, b]| vec![r, g, b, 0]),
+            image(PixelFormat::Bgra8, |[r, g, b]| vec![b, g, r, 0]),
+            image(PixelFormat::Rgba16, |[r, g, b]| {
+                [r, g, b]
+                    .map(|c| c as u16 * 257)
+                    .iter()
+                    .chain(&[0])
+                    .flat_map(|c| c.to_ne_bytes())
+                    .collect()
+            }),
+            image(PixelFormat::Rgba32F, |[r, g, b]| {
+                [r, g, b]
+                    .map(|c| c as f32 / 255.0)
+                    .iter()
+                    .chain(&[0.0])
+                    .flat_map(|c| c.to_ne_bytes())
+                    .collect()
+            }),
+        ];
+        for image in transparent {
+            assert_eq!(run(&image, &options), expected, "{:?}", image.format);
+        }
+
+        // Black at 20% opacity over the default white background.
+        let options = PreprocessOptions::default().with_mode(ResizeMode::Squash);
+        let gray = image(PixelFormat::GrayA8, |_| vec![0, 51]);
+        let expected = [(255.0 * 0.8f32).round() / 255.0; 3].repeat(SIZE * SIZE);
+        assert_eq!(run(&gray, &options), expected);
+    }
+
+    #[test]
+    fn gray_is_expanded_to_rgb() {
+        let luma = |[r, ..]: [u8; 3]| r;
+        let rgb = image(PixelFormat::Rgb8, |p| vec![luma(p); 3]);
+        let gray = [
+            image(PixelFormat::Gray8, |p| vec![luma(p)]),
+            image(PixelFormat::GrayA8, |p| vec![luma(p), 255]),
+            image(PixelFormat::Gray16, |p| {
+                (luma(p) as u16 * 257).to_ne_bytes().to_vec()
+            }),
+            image(PixelFormat::GrayA16, |p| {
+                [luma(p) as u16 * 257, u16::MAX]
+                    .iter()
+                    .flat_map(|c| c.to_ne_bytes())
+                    .collect()
+            }),
+        ];
+        for options in all_options() {
+            let expected = run(&rgb, &options);
+            for image in &gray {
+                assert_eq!(
+                    run(image, &options),
+                    expected,
+                    "{:?} {options:?}",
+                    image.format
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn wide_samples_match_8_bit() {
+        let rgb = image(PixelFormat::Rgb8, |[r, g, b]| vec![r, g, b]);
+        let wide = [
+            image(PixelFormat::Rgb16, |p| {
+                p.iter()
+                    .flat_map(|&c| (c as u16 * 257).to_ne_bytes())
+                    .collect()
+            }),
+            image(PixelFormat::Rgba16, |p| {
+                p.map(|c| c as u16 * 257)
+                    .iter()
+                    .chain(&[u16::MAX])
+                    .flat_map(|c| c.to_ne_bytes())
+                    .collect()
+            }),
+            image(PixelFormat::Rgb32F, |p| {
+                p.iter()
+                    .flat_map(|&c| (c as f32 / 255.0).to_ne_bytes())
+                    .collect()
+            }),
+            image(PixelFormat::Rgba32F, |p| {
+                p.map(|c| c as f32 / 255.0)
+                    .iter()
+                    .chain(&[1.0])
+                    .flat_map(|c| c.to_ne_bytes())
+                    .collect()
+            }),
+        ];
+        for options in all_options() {
+            let expected = run(&rgb, &options);
+            for image in &wide {
+                assert_eq!(
+                    run(image, &options),
+                    expected,
+                    "{:?} {options:?}",
+                    image.format
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn padded_rows_match_packed_rows() {
+        let packed = pattern().concat();
+        let stride = 13 * 3 + 5;
+        // Padding filled with a value that would show up in the output if it was read.
+        let mut padded = vec![0xff; stride * 8 + 13 * 3];
+        for (row, src) in padded.chunks_mut(stride).zip(packed.chunks_exact(13 * 3)) {
+            row[..13 * 3].copy_from_slice(src);
+        }
+
+        let packed = RGBImageView::new(13, 9, &packed).unwrap();
+        let padded = RGBImageView::with_stride(13, 9, stride, &padded).unwrap();
+        for options in all_options() {
+            assert_eq!(
+                run(&padded, &options),
+                run(&packed, &options),
+                "{options:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn yuv_uses_bt601_limited_range() {
+        let cases = [
+            ((16, 128, 128), [0.0, 0.0, 0.0]),
+            ((235, 128, 128), [255.0, 255.0, 255.0]),
+            ((81, 90, 240), [255.0, 0.0, 0.0]),
+            ((145, 54, 34), [0.0, 255.0, 0.0]),
+            ((41, 240, 110), [0.0, 0.0, 255.0]),
+            ((126, 128, 128), [128.0, 128.0, 128.0]),
+        ];
+        for ((y, u, v), expected) in cases {
+            let rgb = yuv_to_rgb(y, u, v);
+            for (c, e) in rgb.into_iter().zip(expected) {
+                assert!((c - e).abs() < 1.0, "{:?}: {rgb:?}", (y, u, v));
+            }
+        }
+        // Out of range values are clamped.
+        assert_eq!(yuv_to_rgb(255, 255, 255)[0], 255.0);
+        assert_eq!(yuv_to_rgb(0, 0, 0)[2], 0.0);
+    }
+
+    #[test]
+    fn yuv_chroma_covers_odd_sizes() {
+        // A 5x3 frame, its chroma is 3x2 with the last column and row covering a single pixel.
+        let (width, height) = (5, 3);
+        let luma = |x: usize, y: usize| (16 + x * 40 + y * 13) as u8;
+        let chroma = |x: usize, y: usize| ((40 + x * 70) as u8, (60 + y * 100 + x * 10) as u8);
+
+        let luma_plane = |stride: usize| {
+            let mut plane = vec![0; stride * height];
+            for (y, row) in plane.chunks_exact_mut(stride).enumerate() {
+                (0..width).for_each(|x| row[x] = luma(x, y));
+            }
+            plane
+        };
+        let nv12 = |stride: usize| {
+            let mut data = luma_plane(stride);
+            for y in 0..2 {
+                let mut row = vec![0; stride];
+                for x in 0..3 {
+                    (row[x * 2], row[x * 2 + 1]) = chroma(x, y);
+                }
+                data.extend(row);
+            }
+            data
+        };
+        let i420 = |stride: usize| {
+            let chroma_stride = stride.div_ceil(2);
+            let mut u = vec![0; chroma_stride * 2];
+            let mut v = vec![0; chroma_stride * 2];
+            for y in 0..2 {
+                for x in 0..3 {
+                    (u[y * chroma_stride + x], v[y * chroma_stride + x]) = chroma(x, y);
+                }
+            }
+            [luma_plane(stride), u, v].concat()
+        };
+
+        let expected = (0..height)
+            .map(|y| {
+                (0..width)
+                    .flat_map(|x| {
+                        let (u, v) = chroma(x / 2, y / 2);
+                        yuv_to_rgb(luma(x, y), u, v)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let check = |image: &dyn Fn(usize, &mut [f32])| {
+            let mut row = vec![0.0; width * 3];
+            for (y, expected) in expected.iter().enumerate() {
+                image(y, &mut row);
+                assert_eq!(&row, expected, "row {y}");
+            }
+        };
+
+        let (w, h) = (width as u32, height as u32);
+        let image = NV12Image::new(w, h, nv12(6)).unwrap();
+        check(&|y, row| load_row(&image, y, [0; 3], row));
+        let image = NV12Image::with_stride(w, h, 8, nv12(8)).unwrap();
+        check(&|y, row| load_row(&image, y, [0; 3], row));
+        let image = I420Image::new(w, h, i420(5)).unwrap();
+        check(&|y, row| load_row(&image, y, [0; 3], row));
+        let image = I420Image::with_stride(w, h, 7, i420(7)).unwrap();
+        check(&|y, row| load_row(&image, y, [0; 3], row));
+    }
+
+    #[test]
+    fn config_requires_positive_size() {
+        let options = PreprocessOptions::default();
+        for size in [0, -224] {
+            assert!(matches!(
+                PreprocessConfig::new(size, [0.0; 3], [1.0; 3], options.clone()),
+                Err(Error::PreprocessConfig(_))
+            ));
+        }
+        let config = PreprocessConfig::new(SIZE as i32, [0.0; 3], [1.0; 3], options).unwrap();
+        let image = image(PixelFormat::Rgb8, |[r, g, b]| vec![r, g, b]);
+        let blob = config.preprocess_image(&image).unwrap();
+        assert_eq!(blob.as_slice(), run(&image, config.options()));
+    }
+
+    #[test]
+    fn config_rejects_invalid_normalization() {
+        let options = PreprocessOptions::default();
+        let new = |mean, std| PreprocessConfig::new(224, mean, std, options.clone());
+        for std in [[0.5, 0.0, 0.5], [f32::NAN; 3], [0.5, f32::INFINITY, 0.5]] {
+            assert!(matches!(
+                new([0.5; 3], std),
+                Err(Error::PreprocessConfig(_))
+            ));
+        }
+        assert!(matches!(
+            new([f32::NAN, 0.5, 0.5], [0.5; 3]),
+            Err(Error::PreprocessConfig(_))
+        ));
+        let config = new([0.5; 3], [-0.5; 3]).unwrap();
+        assert_eq!((config.mean(), config.std()), ([0.5; 3], [-0.5; 3]));
+    }
+
+    #[test]
+    fn renormalize_matches_preprocessing_with_the_target_normalization() {
+        let options = PreprocessOptions::default();
+        let openai = PreprocessConfig::new(
+            SIZE as i32,
+            [0.48145466, 0.4578275, 0.40821073],
+            [0.26862954, 0.2613026, 0.2757771],
+            options.clone(),
+        )
+        .unwrap();
+        let siglip = PreprocessConfig::new(SIZE as i32, [0.5; 3], [0.5; 3], options).unwrap();
+        let image = image(PixelFormat::Rgb8, |[r, g, b]| vec![r, g, b]);
+
+        let mut data = openai.preprocess_image(&image).unwrap().as_slice().to_vec();
+        siglip.renormalize(&mut data, openai.mean(), openai.std());
+        let expected = siglip.preprocess_image(&image).unwrap();
+        for (a, e) in data.iter().zip(expected.as_slice()) {
+            assert!((a - e).abs() < 1e-5, "{a} != {e}");
+        }
+
+        // Inputs already in the target normalization are left alone.
+        let before = data.clone();
+        siglip.renormalize(&mut data, [0.5; 3], [0.5; 3]);
+        assert_eq!(data, before);
+    }
+
+    #[test]
+    fn options_round_trip() {
+        let options = PreprocessOptions::default()
+            .with_mode(ResizeMode::Letterbox)
+            .with_filter(FilterType::Bilinear)
+            .with_pad_color([1, 2, 3])
+            .with_background([4, 5, 6]);
+        assert_eq!(options.mode(), ResizeMode::Letterbox);
+        assert_eq!(options.filter(), FilterType::Bilinear);
+        assert_eq!(options.pad_color(), [1, 2, 3]);
+        assert_eq!(options.background(), [4, 5, 6]);
+
+        let default = PreprocessOptions::default();
+        assert_eq!(default.mode(), ResizeMode::CenterCrop);
+        assert_eq!(default.filter(), FilterType::Bicubic);
+        assert_eq!(default.pad_color(), [0; 3]);
+        assert_eq!(default.background(), [255; 3]);
+    }
+
+    #[test]
+    fn short_data_is_rejected() {
+        let mut image = image(PixelFormat::Rgba8, |[r, g, b]| vec![r, g, b, 255]);
+        image.data.pop();
+        let mut dest = vec![0.0; SIZE * SIZE * 3];
+        let options = PreprocessOptions::default();
+        let result = preprocess(&image, SIZE, &[0.0; 3], &[1.0; 3], &options, &mut dest);
+        assert!(matches!(result, Err(Error::Preprocess)));
+    }
+}
@@ -0,0 +1,59 @@
+use std::ffi::CString;
+use std::path::Path;
+
+use super::Error;
+
+/// ggml quantization types supported by clip.cpp, with their `ggml_type` values.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuantType {
+    Q4_0 = 2,
+    Q4_1 = 3,
+    Q5_0 = 6,
+    Q5_1 = 7,
+    Q8_0 = 8,
+}
+
+/// Quantizes the GGUF model at `input` to `quant_type` and writes the result to `output`.
+pub fn quantize<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    quant_type: QuantType,
+) -> Result<(), Error> {
+    let input = input.as_ref();
+    if !input.exists() {
+        return Err(Error::PathNotFound);
+    }
+    let (input, output) = (path_to_cstring(input)?, path_to_cstring(output.as_ref())?);
+
+    let ok = unsafe {
+        clip_cpp_sys::clip_model_quantize(input.as_ptr(), output.as_ptr(), quant_type as i32)
+    };
+    if !ok {
+        return Err(Error::Quantize);
+    }
+
+    Ok(())
+}
+
+/// Converts `path` for clip.cpp, failing with [`Error::NulByte`] if it contains a nul byte.
+pub(crate) fn path_to_cstring(path: &Path) -> Result<CString, Error> {
+    use std::os::unix::ffi::OsStrExt;
+    CString::new(path.as_os_str().as_bytes()).map_err(|e| Error::NulByte {
+        position: e.nul_position(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nul_byte_in_path_is_an_error() {
+        let input = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        assert!(matches!(
+            quantize(input, "model\0.gguf", QuantType::Q4_0),
+            Err(Error::NulByte { position: 5 })
+        ));
+    }
+}
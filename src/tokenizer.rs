@@ -0,0 +1,855 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use super::gguf::{self, Header};
+use super::Error;
+
+pub(crate) const START_OF_TEXT: &str = "<|startoftext|>";
+pub(crate) const END_OF_TEXT: &str = "<|endoftext|>";
+const END_OF_WORD: &str = "</w>";
+
+/// Context length of the OpenAI CLIP text encoders.
+const DEFAULT_CONTEXT_LENGTH: usize = 77;
+
+fn is_printable(byte: u8) -> bool {
+    matches!(byte, b'!'..=b'~' | 0xa1..=0xac | 0xae..=0xff)
+}
+
+/// The 256 characters of the byte-level BPE alphabet, in vocabulary order: printable bytes
+/// first, then the remaining bytes mapped from U+0100.
+fn alphabet() -> Vec<(u8, char)> {
+    let printable = (0..=255u8)
+        .filter(|&b| is_printable(b))
+        .map(|b| (b, b as char));
+    let rest = (0..=255u8)
+        .filter(|&b| !is_printable(b))
+        .enumerate()
+        .map(|(i, b)| (b, char::from_u32(0x100 + i as u32).unwrap()));
+    printable.chain(rest).collect()
+}
+
+/// Maps a character of the byte-level BPE alphabet back to the byte it stands for.
+///
+/// Printable bytes stand for themselves, the others are numbered from U+0100 in byte order.
+fn char_to_byte(c: char) -> Option<u8> {
+    match u8::try_from(c) {
+        Ok(byte) if is_printable(byte) => Some(byte),
+        _ => {
+            let offset = (c as u32).checked_sub(0x100)?;
+            (0..=255u8)
+                .filter(|&b| !is_printable(b))
+                .nth(offset as usize)
+        }
+    }
+}
+
+/// Token ids of a tokenized text.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Tokens {
+    ids: Vec<i32>,
+    truncated: bool,
+}
+
+impl Tokens {
+    /// Wraps raw token ids, they are checked against the model vocabulary when encoded.
+    pub fn from_ids(ids: Vec<i32>) -> Self {
+        Self {
+            ids,
+            truncated: false,
+        }
+    }
+
+    pub fn ids(&self) -> &[i32] {
+        &self.ids
+    }
+
+    pub fn into_ids(self) -> Vec<i32> {
+        self.ids
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Whether the text did not fit in the model context and was cut short.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Cuts the tokens down to `max_len`, keeping the end of text marker as the last token.
+    pub(crate) fn truncate(&mut self, max_len: usize) {
+        if self.ids.len() > max_len && max_len > 0 {
+            let end = self.ids[self.ids.len() - 1];
+            self.ids.truncate(max_len);
+            self.ids[max_len - 1] = end;
+            self.truncated = true;
+        }
+    }
+}
+
+impl From<Vec<i32>> for Tokens {
+    fn from(ids: Vec<i32>) -> Self {
+        Self::from_ids(ids)
+    }
+}
+
+impl AsRef<[i32]> for Tokens {
+    fn as_ref(&self) -> &[i32] {
+        &self.ids
+    }
+}
+
+/// The token strings of a CLIP vocabulary, indexed by token id.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Vocab {
+    tokens: Vec<String>,
+}
+
+impl Vocab {
+    fn new(tokens: Vec<String>) -> Self {
+        Self { tokens }
+    }
+
+    /// Reads the token list from GGUF metadata, a model without a text encoder yields an empty
+    /// vocabulary.
+    pub(crate) fn from_header(header: &Header) -> Result<Self, Error> {
+        let tokens = match header.get(gguf::KEY_TOKENS) {
+            Some(tokens) => tokens
+                .as_array()
+                .ok_or(Error::Gguf("token list is not an array"))?
+                .iter()
+                .map(|t| {
+                    t.as_str()
+                        .map(str::to_owned)
+                        .ok_or(Error::Gguf("token is not a string"))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
+        Ok(Self::new(tokens))
+    }
+
+    pub(crate) fn token(&self, id: i32) -> Result<&str, Error> {
+        usize::try_from(id)
+            .ok()
+            .and_then(|id| self.tokens.get(id))
+            .map(String::as_str)
+            .ok_or(Error::InvalidToken { id })
+    }
+
+    /// Decodes token ids back into text, dropping the start and end of text markers.
+    pub(crate) fn decode(&self, ids: &[i32]) -> Result<String, Error> {
+        let mut bytes = Vec::new();
+        for &id in ids {
+            let token = self.token(id)?;
+            if token == START_OF_TEXT || token == END_OF_TEXT {
+                continue;
+            }
+            let (word, end) = match token.strip_suffix(END_OF_WORD) {
+                Some(word) => (word, true),
+                None => (token, false),
+            };
+            bytes.extend(word.chars().filter_map(char_to_byte));
+            if end {
+                bytes.push(b' ');
+            }
+        }
+        Ok(String::from_utf8_lossy(&bytes).trim_end().to_owned())
+    }
+}
+
+/// Applies the cleanup of the reference CLIP tokenizer: a subset of ftfy's fixes, HTML
+/// unescaping, whitespace collapsing and lowercasing.
+fn clean(text: &str) -> String {
+    let fixed = text
+        .chars()
+        .flat_map(|c| {
+            let fixed = match c {
+                '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' => "'",
+                '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{201f}' => "\"",
+                '\u{fb00}' => "ff",
+                '\u{fb01}' => "fi",
+                '\u{fb02}' => "fl",
+                '\u{fb03}' => "ffi",
+                '\u{fb04}' => "ffl",
+                '\u{fb05}' | '\u{fb06}' => "st",
+                _ => "",
+            };
+            let c = match c as u32 {
+                // Fullwidth ASCII variants and the ideographic space.
+                0xff01..=0xff5e => char::from_u32(c as u32 - 0xfee0).unwrap(),
+                0x3000 => ' ',
+                _ => c,
+            };
+            // Control characters other than whitespace are dropped.
+            let c = (fixed.is_empty() && (c.is_whitespace() || !c.is_control())).then_some(c);
+            fixed.chars().chain(c)
+        })
+        .collect::<String>();
+    let unescaped = unescape_html(&unescape_html(&fixed));
+    unescaped
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Replaces HTML character references, both named and numeric.
+fn unescape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| {
+                let entity = &rest[1..end + 1];
+                let c = match entity {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some('\u{a0}'),
+                    _ => match entity.strip_prefix('#') {
+                        Some(n) => match n.strip_prefix(['x', 'X']) {
+                            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                            None => n.parse().ok(),
+                        }
+                        .and_then(char::from_u32),
+                        None => None,
+                    },
+                };
+                c.map(|c| (c, end + 2))
+            });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Splits cleaned text into words the way the reference CLIP pattern does.
+fn split_words(text: &str) -> Vec<&str> {
+    const CONTRACTIONS: [&str; 7] = ["'s", "'t", "'re", "'ve", "'m", "'ll", "'d"];
+    let is_symbol = |c: char| !c.is_whitespace() && !c.is_alphabetic() && !c.is_numeric();
+
+    let mut words = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let len = if let Some(special) = [START_OF_TEXT, END_OF_TEXT]
+            .into_iter()
+            .chain(CONTRACTIONS)
+            .find(|p| rest.starts_with(p))
+        {
+            special.len()
+        } else if c.is_alphabetic() {
+            rest.find(|c: char| !c.is_alphabetic())
+                .unwrap_or(rest.len())
+        } else if c.is_numeric() {
+            c.len_utf8()
+        } else if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        } else {
+            rest.find(|c: char| !is_symbol(c)).unwrap_or(rest.len())
+        };
+        words.push(&rest[..len]);
+        rest = &rest[len..];
+    }
+    words
+}
+
+/// Splits text into words the way clip.cpp does.
+///
+/// This is the reference CLIP pattern evaluated with ASCII character classes, so every byte of a
+/// non-ASCII character counts as a symbol and digits are not split up. Words keep their leading
+/// space and whitespace runs are returned as words of their own.
+fn split_words_native(text: &[u8]) -> Vec<&[u8]> {
+    const CONTRACTIONS: [&[u8]; 7] = [b"'s", b"'t", b"'re", b"'ve", b"'m", b"'ll", b"'d"];
+    let run =
+        |bytes: &[u8], f: fn(&u8) -> bool| bytes.iter().position(|b| !f(b)).unwrap_or(bytes.len());
+    let is_symbol = |b: &u8| !b.is_ascii_whitespace() && !b.is_ascii_alphanumeric();
+
+    let mut words = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let space = usize::from(rest[0] == b' ');
+        let len = if let Some(c) = CONTRACTIONS.into_iter().find(|c| rest.starts_with(c)) {
+            c.len()
+        } else if rest.get(space).is_some_and(u8::is_ascii_alphabetic) {
+            space + run(&rest[space..], u8::is_ascii_alphabetic)
+        } else if rest.get(space).is_some_and(u8::is_ascii_digit) {
+            space + run(&rest[space..], u8::is_ascii_digit)
+        } else if rest.get(space).is_some_and(is_symbol) {
+            space + run(&rest[space..], is_symbol)
+        } else {
+            // `\s+(?!\S)` backs off one byte so the last space starts the next word.
+            let n = run(rest, u8::is_ascii_whitespace);
+            if n > 1 && n < rest.len() {
+                n - 1
+            } else {
+                n
+            }
+        };
+        words.push(&rest[..len]);
+        rest = &rest[len..];
+    }
+    words
+}
+
+/// A pure-Rust implementation of the CLIP byte-level BPE tokenizer.
+///
+/// [`encode`](Self::encode) follows the reference Python tokenizer: it cleans and lowercases the
+/// text and applies the BPE merges. clip.cpp does neither, it looks each word up whole and falls
+/// back to a greedy longest match, so for text with uppercase letters, digits, non-ASCII
+/// characters or words missing from the vocabulary the two disagree.
+/// [`encode_native`](Self::encode_native) reproduces clip.cpp and returns the same ids as
+/// [`Model::tokenize`](crate::Model::tokenize). Either output can be passed to
+/// [`Model::try_encode_tokens`](crate::Model::try_encode_tokens).
+/// Reads `clip.text.context_length`, [`DEFAULT_CONTEXT_LENGTH`] if it is missing.
+///
+/// Fails with [`Error::GgufKey`] unless the value is a positive integer.
+fn context_length(header: &Header) -> Result<usize, Error> {
+    let Some(value) = header.get(gguf::KEY_N_POSITIONS) else {
+        return Ok(DEFAULT_CONTEXT_LENGTH);
+    };
+    value
+        .as_u64()
+        .filter(|&n| n > 0)
+        .and_then(|n| usize::try_from(n).ok())
+        .ok_or(Error::GgufKey(gguf::KEY_N_POSITIONS))
+}
+
+#[derive(Debug, Clone)]
+pub struct Tokenizer {
+    vocab: Vocab,
+    encoder: HashMap<String, i32>,
+    /// Rank and merged token of every mergeable pair.
+    merges: HashMap<(i32, i32), (usize, i32)>,
+    byte_chars: [char; 256],
+    context_length: usize,
+}
+
+impl Tokenizer {
+    /// Reads the vocabulary from a GGUF model file.
+    ///
+    /// Merges are taken from `tokenizer.ggml.merges` when present, otherwise they are recovered
+    /// from the order of the vocabulary. Fails with [`Error::GgufKey`] if the context length is
+    /// present but not a positive integer.
+    pub fn from_gguf<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let header = Header::read_file(path)?;
+        let vocab = Vocab::from_header(&header)?;
+        if vocab.tokens.is_empty() {
+            return Err(Error::Vocab("model has no tokenizer"));
+        }
+        let context_length = context_length(&header)?;
+        let merges = match header.get(gguf::KEY_MERGES) {
+            Some(merges) => Some(
+                merges
+                    .as_array()
+                    .ok_or(Error::Gguf("merge list is not an array"))?
+                    .iter()
+                    .map(|m| m.as_str().ok_or(Error::Gguf("merge is not a string")))
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            None => None,
+        };
+
+        let mut tokenizer = Self::new(vocab, context_length);
+        match merges {
+            Some(merges) => tokenizer.add_merges(merges)?,
+            None => tokenizer.recover_merges()?,
+        }
+        Ok(tokenizer)
+    }
+
+    /// Reads the merge list shipped with OpenAI CLIP (`bpe_simple_vocab_16e6.txt`, decompressed)
+    /// and rebuilds the vocabulary from it.
+    pub fn from_openai_bpe<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        // The reference tokenizer uses the first 48894 merges, skipping the version header.
+        Self::from_openai_merges(&fs::read_to_string(path)?)
+    }
+
+    fn from_openai_merges(text: &str) -> Result<Self, Error> {
+        const MERGES: usize = 49152 - 256 - 2;
+        let merges = text.lines().skip(1).take(MERGES).collect::<Vec<_>>();
+
+        let alphabet = alphabet().into_iter().map(|(_, c)| c.to_string());
+        let mut tokens = alphabet.collect::<Vec<_>>();
+        tokens.extend(tokens.clone().into_iter().map(|t| t + END_OF_WORD));
+        for merge in &merges {
+            tokens.push(merge.split(' ').collect());
+        }
+        tokens.push(START_OF_TEXT.to_owned());
+        tokens.push(END_OF_TEXT.to_owned());
+
+        let mut tokenizer = Self::new(Vocab::new(tokens), DEFAULT_CONTEXT_LENGTH);
+        tokenizer.add_merges(merges)?;
+        Ok(tokenizer)
+    }
+
+    fn new(vocab: Vocab, context_length: usize) -> Self {
+        let encoder = vocab
+            .tokens
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.clone(), id as i32))
+            .collect();
+        let mut byte_chars = ['\0'; 256];
+        for (byte, c) in alphabet() {
+            byte_chars[byte as usize] = c;
+        }
+        Self {
+            vocab,
+            encoder,
+            merges: HashMap::new(),
+            byte_chars,
+            context_length,
+        }
+    }
+
+    fn id(&self, token: &str) -> Result<i32, Error> {
+        self.encoder.get(token).copied().ok_or(Error::Vocab(
+            "merge refers to a token missing from the vocabulary",
+        ))
+    }
+
+    fn add_merges<'a, I: IntoIterator<Item = &'a str>>(&mut self, merges: I) -> Result<(), Error> {
+        for (rank, merge) in merges.into_iter().enumerate() {
+            let (a, b) = merge
+                .split_once(' ')
+                .ok_or(Error::Vocab("merge is not a pair"))?;
+            let pair = (self.id(a)?, self.id(b)?);
+            let merged = self.id(&format!("{a}{b}"))?;
+            self.merges.insert(pair, (rank, merged));
+        }
+        Ok(())
+    }
+
+    /// Recovers the merges from a vocabulary laid out like the OpenAI one: the byte alphabet,
+    /// the byte alphabet with end-of-word markers, one token per merge in rank order and the two
+    /// special tokens.
+    fn recover_merges(&mut self) -> Result<(), Error> {
+        let tokens = &self.vocab.tokens;
+        let n = tokens.len();
+        if n < 514 || tokens[n - 2] != START_OF_TEXT || tokens[n - 1] != END_OF_TEXT {
+            return Err(Error::Vocab("unrecognized vocabulary layout"));
+        }
+
+        for rank in 0..n - 514 {
+            let id = (512 + rank) as i32;
+            let token = self.vocab.tokens[id as usize].clone();
+            let (word, end) = match token.strip_suffix(END_OF_WORD) {
+                Some(word) => (word, true),
+                None => (token.as_str(), false),
+            };
+            // Applying the merges of lower rank leaves the pair this token was merged from.
+            let symbols = self.bpe(&self.symbols(word, end)?);
+            let pair = match symbols[..] {
+                [a, b] => (a, b),
+                _ => {
+                    // Fall back to the first split into two lower ranked tokens.
+                    let chars = word.char_indices().skip(1).map(|(i, _)| i);
+                    chars
+                        .filter_map(|i| {
+                            let suffix =
+                                format!("{}{}", &word[i..], if end { END_OF_WORD } else { "" });
+                            let a = *self.encoder.get(&word[..i])?;
+                            let b = *self.encoder.get(&suffix)?;
+                            (a < id && b < id).then_some((a, b))
+                        })
+                        .next()
+                        .ok_or(Error::Vocab("cannot recover merges from vocabulary"))?
+                }
+            };
+            self.merges.insert(pair, (rank, id));
+        }
+        Ok(())
+    }
+
+    /// Maps each character of a byte-encoded word to its token, marking the last one as the end
+    /// of the word when `end` is set.
+    fn symbols(&self, word: &str, end: bool) -> Result<Vec<i32>, Error> {
+        let mut chars = word.chars().peekable();
+        let mut symbols = Vec::new();
+        let mut buf = String::new();
+        while let Some(c) = chars.next() {
+            buf.clear();
+            buf.push(c);
+            if end && chars.peek().is_none() {
+                buf.push_str(END_OF_WORD);
+            }
+            symbols.push(
+                *self
+                    .encoder
+                    .get(&buf)
+                    .ok_or(Error::Vocab("vocabulary is missing a byte token"))?,
+            );
+        }
+        Ok(symbols)
+    }
+
+    /// Repeatedly merges the lowest ranked adjacent pair until no known pair is left.
+    fn bpe(&self, symbols: &[i32]) -> Vec<i32> {
+        let mut word = symbols.to_vec();
+        while word.len() > 1 {
+            let best = word
+                .windows(2)
+                .filter_map(|w| self.merges.get(&(w[0], w[1])).map(|m| ((w[0], w[1]), *m)))
+                .min_by_key(|(_, (rank, _))| *rank);
+            let Some(((a, b), (_, merged))) = best else {
+                break;
+            };
+
+            let mut next = Vec::with_capacity(word.len());
+            let mut i = 0;
+            while i < word.len() {
+                if i + 1 < word.len() && word[i] == a && word[i + 1] == b {
+                    next.push(merged);
+                    i += 2;
+                } else {
+                    next.push(word[i]);
+                    i += 1;
+                }
+            }
+            word = next;
+        }
+        word
+    }
+
+    /// Cleans and tokenizes `text`, adding the start and end of text markers and truncating it to
+    /// the context length.
+    pub fn encode<T: AsRef<str>>(&self, text: T) -> Result<Tokens, Error> {
+        let mut ids = vec![self.id(START_OF_TEXT)?];
+        for word in split_words(&clean(text.as_ref())) {
+            if word == START_OF_TEXT || word == END_OF_TEXT {
+                ids.push(self.id(word)?);
+                continue;
+            }
+            let word = word
+                .bytes()
+                .map(|b| self.byte_chars[b as usize])
+                .collect::<String>();
+            ids.extend(self.bpe(&self.symbols(&word, true)?));
+        }
+        ids.push(self.id(END_OF_TEXT)?);
+
+        let mut tokens = Tokens::from_ids(ids);
+        tokens.truncate(self.context_length);
+        Ok(tokens)
+    }
+
+    /// Tokenizes `text` the way clip.cpp does, giving the same ids as
+    /// [`Model::tokenize`](crate::Model::tokenize).
+    ///
+    /// Each word is looked up whole with its end of word marker, otherwise it is split greedily
+    /// into the longest tokens matching its raw bytes. Bytes no token matches are dropped.
+    pub fn encode_native<T: AsRef<str>>(&self, text: T) -> Result<Tokens, Error> {
+        let id = |bytes: &[u8]| {
+            std::str::from_utf8(bytes)
+                .ok()
+                .and_then(|token| self.encoder.get(token))
+                .copied()
+        };
+
+        let mut ids = vec![self.id(START_OF_TEXT)?];
+        for word in split_words_native(text.as_ref().as_bytes()) {
+            let whole = [
+                word.strip_prefix(b" ").unwrap_or(word),
+                END_OF_WORD.as_bytes(),
+            ]
+            .concat();
+            if let Some(id) = id(&whole) {
+                ids.push(id);
+                continue;
+            }
+            let mut i = 0;
+            while i < word.len() {
+                match (i + 1..=word.len())
+                    .rev()
+                    .find_map(|j| Some((id(&word[i..j])?, j)))
+                {
+                    Some((id, j)) => {
+                        ids.push(id);
+                        i = j;
+                    }
+                    None => i += 1,
+                }
+            }
+        }
+        ids.push(self.id(END_OF_TEXT)?);
+
+        let mut tokens = Tokens::from_ids(ids);
+        tokens.truncate(self.context_length);
+        Ok(tokens)
+    }
+
+    /// Decodes token ids back into text, dropping the start and end of text markers.
+    pub fn decode(&self, tokens: &Tokens) -> Result<String, Error> {
+        self.vocab.decode(tokens.ids())
+    }
+
+    pub fn vocab_size(&self) -> usize {
+        self.vocab.tokens.len()
+    }
+
+    /// Maximum number of tokens, including the start and end of text markers.
+    pub fn context_length(&self) -> usize {
+        self.context_length
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_keeps_end_of_text_marker() {
+        let mut tokens = Tokens::from_ids(vec![49406, 1, 2, 3, 49407]);
+        tokens.truncate(5);
+        assert_eq!(tokens.ids(), [49406, 1, 2, 3, 49407]);
+        assert!(!tokens.is_truncated());
+
+        tokens.truncate(3);
+        assert_eq!(tokens.ids(), [49406, 1, 49407]);
+        assert!(tokens.is_truncated());
+
+        let mut tokens = Tokens::from_ids(vec![49406, 1, 49407]);
+        tokens.truncate(0);
+        assert_eq!(tokens.len(), 3);
+        assert!(!tokens.is_truncated());
+    }
+
+    #[test]
+    fn context_length_must_be_a_positive_integer() {
+        let header = |value| Header::test(vec![(gguf::KEY_N_POSITIONS.to_owned(), value)]);
+        for (value, expected) in [
+            (gguf::Value::U32(77), 77),
+            (gguf::Value::I32(64), 64),
+            (gguf::Value::U64(1), 1),
+            (gguf::Value::I16(77), 77),
+        ] {
+            assert_eq!(context_length(&header(value)).unwrap(), expected);
+        }
+        for value in [
+            gguf::Value::I32(-1),
+            gguf::Value::I64(i64::MIN),
+            gguf::Value::U32(0),
+            gguf::Value::F32(77.0),
+            gguf::Value::String("77".to_owned()),
+        ] {
+            assert!(
+                matches!(
+                    context_length(&header(value.clone())),
+                    Err(Error::GgufKey(gguf::KEY_N_POSITIONS))
+                ),
+                "{value:?}"
+            );
+        }
+        assert_eq!(
+            context_length(&Header::test(Vec::new())).unwrap(),
+            DEFAULT_CONTEXT_LENGTH
+        );
+    }
+
+    #[test]
+    fn vocab_decode_joins_words_and_bytes() {
+        let vocab = Vocab::new(
+            [
+                START_OF_TEXT,
+                "hel",
+                "lo</w>",
+                "wor",
+                "ld</w>",
+                "!</w>",
+                // "é" as byte-level characters, and a space mapped to U+0120.
+                "caf",
+                "Ã©</w>",
+                "aĠb</w>",
+                END_OF_TEXT,
+            ]
+            .map(str::to_owned)
+            .to_vec(),
+        );
+
+        assert_eq!(
+            vocab.decode(&[0, 1, 2, 3, 4, 5, 9]).unwrap(),
+            "hello world !"
+        );
+        assert_eq!(vocab.decode(&[6, 7]).unwrap(), "café");
+        assert_eq!(vocab.decode(&[8]).unwrap(), "a b");
+        assert_eq!(vocab.decode(&[]).unwrap(), "");
+        assert!(matches!(
+            vocab.decode(&[1, 10]),
+            Err(Error::InvalidToken { id: 10 })
+        ));
+        assert!(matches!(
+            vocab.decode(&[-1]),
+            Err(Error::InvalidToken { id: -1 })
+        ));
+    }
+
+    // A merge list in the layout of `bpe_simple_vocab_16e6.txt`.
+    const MERGES: &str = "\
+#version: 0.2
+h e
+l l
+he ll
+hell o</w>
+w o
+wo r
+l d</w>
+wor ld</w>
+";
+
+    fn tokenizer() -> Tokenizer {
+        Tokenizer::from_openai_merges(MERGES).unwrap()
+    }
+
+    fn tokens<'a>(tokenizer: &'a Tokenizer, tokens: &Tokens) -> Vec<&'a str> {
+        let ids = tokens.ids().iter();
+        ids.map(|&id| tokenizer.vocab.token(id).unwrap()).collect()
+    }
+
+    #[test]
+    fn clean_fixes_and_lowercases_text() {
+        assert_eq!(
+            clean(
+                "  Fancy \u{201c}Quotes\u{201d}\tand &amp;amp; \u{fb01}ne \u{ff21}\u{ff22}\u{7}  "
+            ),
+            "fancy \"quotes\" and & fine ab"
+        );
+        assert_eq!(clean("&#72;&#x49; &bogus; & done"), "hi &bogus; & done");
+    }
+
+    #[test]
+    fn split_words_follows_the_reference_pattern() {
+        assert_eq!(
+            split_words("a photo's 123 ok!! caf\u{e9}<|endoftext|>"),
+            [
+                "a",
+                "photo",
+                "'s",
+                "1",
+                "2",
+                "3",
+                "ok",
+                "!!",
+                "caf\u{e9}",
+                END_OF_TEXT
+            ]
+        );
+        assert_eq!(split_words("   "), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn split_words_native_uses_ascii_classes() {
+        let words = split_words_native("Hi, it's 2024  caf\u{e9}\t!".as_bytes());
+        let words = words.iter().map(|w| std::str::from_utf8(w).unwrap());
+        assert_eq!(
+            words.collect::<Vec<_>>(),
+            ["Hi", ",", " it", "'s", " 2024", " ", " caf", "\u{e9}", "\t", "!"]
+        );
+    }
+
+    #[test]
+    fn encode_applies_merges_by_rank() {
+        let tokenizer = tokenizer();
+        assert_eq!(tokenizer.vocab_size(), 512 + 8 + 2);
+        assert_eq!(tokenizer.context_length(), DEFAULT_CONTEXT_LENGTH);
+
+        let encoded = tokenizer.encode("Hello, World! held").unwrap();
+        assert_eq!(
+            tokens(&tokenizer, &encoded),
+            [
+                START_OF_TEXT,
+                "hello</w>",
+                ",</w>",
+                "world</w>",
+                "!</w>",
+                "he",
+                "ld</w>",
+                END_OF_TEXT
+            ]
+        );
+        assert_eq!(tokenizer.decode(&encoded).unwrap(), "hello , world ! held");
+
+        let encoded = tokenizer.encode("hello ".repeat(100)).unwrap();
+        assert!(encoded.is_truncated());
+        assert_eq!(encoded.len(), DEFAULT_CONTEXT_LENGTH);
+    }
+
+    #[test]
+    fn encode_native_matches_whole_words_then_longest_prefixes() {
+        let tokenizer = tokenizer();
+        let encoded = tokenizer.encode_native("Hello, world!  hello").unwrap();
+        assert_eq!(
+            tokens(&tokenizer, &encoded),
+            [
+                START_OF_TEXT,
+                "H",
+                "e",
+                "ll",
+                "o",
+                ",</w>",
+                "world</w>",
+                "!</w>",
+                "hello</w>",
+                END_OF_TEXT
+            ]
+        );
+
+        // Raw UTF-8 is matched against the byte-level alphabet, so "\u{e9}" is read as the token
+        // of the byte 0xe9.
+        let encoded = tokenizer.encode_native("caf\u{e9} 42").unwrap();
+        assert_eq!(
+            tokens(&tokenizer, &encoded),
+            [
+                START_OF_TEXT,
+                "c",
+                "a",
+                "f",
+                "\u{e9}</w>",
+                "4",
+                "2",
+                END_OF_TEXT
+            ]
+        );
+    }
+
+    #[test]
+    fn recover_merges_matches_merge_list() {
+        let expected = tokenizer();
+        let mut recovered = Tokenizer::new(expected.vocab.clone(), DEFAULT_CONTEXT_LENGTH);
+        recovered.recover_merges().unwrap();
+        assert_eq!(recovered.merges, expected.merges);
+    }
+
+    #[test]
+    fn recover_merges_rejects_unknown_layouts() {
+        let mut short = Tokenizer::new(Vocab::new(vec!["a".to_owned()]), 77);
+        assert!(matches!(short.recover_merges(), Err(Error::Vocab(_))));
+
+        // "xyz" can't be split into two tokens that come before it.
+        let mut tokens = tokenizer().vocab.tokens;
+        tokens.insert(512, "xyz".to_owned());
+        let mut unmergeable = Tokenizer::new(Vocab::new(tokens), 77);
+        assert!(matches!(unmergeable.recover_merges(), Err(Error::Vocab(_))));
+    }
+}
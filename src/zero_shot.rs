@@ -0,0 +1,475 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use ndarray::{Array2, Axis};
+
+use super::params::ModelParams;
+use super::{
+    Embedding, Embeddings, Error, Image, Modality, Model, PreprocessOptions, TextParams,
+    VisionParams,
+};
+
+/// Prompt template used when none is configured, `{}` is replaced by the label.
+pub const DEFAULT_TEMPLATE: &str = "a photo of a {}.";
+
+/// Temperature applied to cosine similarities before the softmax.
+///
+/// clip.cpp does not export the learned `logit_scale`, trained CLIP models clamp it to this value.
+pub const DEFAULT_LOGIT_SCALE: f32 = 100.0;
+
+/// Options for [`Model::zero_shot_classify_with`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZeroShotOptions {
+    pub(crate) templates: Vec<String>,
+    pub(crate) logit_scale: f32,
+    pub(crate) preprocess: Option<PreprocessOptions>,
+}
+
+impl Default for ZeroShotOptions {
+    fn default() -> Self {
+        Self {
+            templates: vec![DEFAULT_TEMPLATE.to_owned()],
+            logit_scale: DEFAULT_LOGIT_SCALE,
+            preprocess: None,
+        }
+    }
+}
+
+impl ZeroShotOptions {
+    /// Prompt templates, `{}` is replaced by the label. Embeddings of all templates are averaged
+    /// per label.
+    pub fn templates<T>(mut self, templates: T) -> Self
+    where
+        T: IntoIterator,
+        T::Item: Into<String>,
+    {
+        self.templates = templates.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn logit_scale(mut self, logit_scale: f32) -> Self {
+        self.logit_scale = logit_scale;
+        self
+    }
+
+    /// Preprocessing of the classified image, the options the model was built with by default.
+    pub fn preprocess(mut self, preprocess: PreprocessOptions) -> Self {
+        self.preprocess = Some(preprocess);
+        self
+    }
+}
+
+/// Probability assigned to a label by zero-shot classification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelScore {
+    label: String,
+    index: usize,
+    probability: f32,
+}
+
+impl LabelScore {
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Position of the label in the label set.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn probability(&self) -> f32 {
+        self.probability
+    }
+}
+
+/// Encodes every label with every template and averages the normalized prompt embeddings into a
+/// single normalized embedding per label.
+fn label_embeddings<L: AsRef<str>>(
+    model: &Model,
+    labels: &[L],
+    templates: &[String],
+) -> Result<Embeddings, Error> {
+    if templates.is_empty() {
+        return Err(Error::NoTemplates);
+    }
+
+    let dim = model.vision_params().projection_dim() as usize;
+    let mut embeddings = Array2::zeros((labels.len(), dim));
+    for (label, mut row) in labels.iter().zip(embeddings.rows_mut()) {
+        let prompts = templates.iter().map(|t| t.replace("{}", label.as_ref()));
+        let prompts = model.encode_texts(prompts, true)?;
+        row.assign(&prompts.as_array().mean_axis(Axis(0)).unwrap());
+    }
+
+    Ok(Embeddings::new(embeddings, false, Modality::Text, model.params()).normalized())
+}
+
+const MAGIC: &[u8; 8] = b"CLIPZSC\0";
+const VERSION: u32 = 1;
+
+/// A zero-shot classification head: prompt-ensembled text embeddings for a fixed label set.
+///
+/// The head records the hyperparameters of the model it was built with, it can be saved to disk
+/// and reloaded without running the text encoder again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZeroShotClassifier {
+    labels: Vec<String>,
+    embeddings: Embeddings,
+    logit_scale: f32,
+    text_params: TextParams,
+    vision_params: VisionParams,
+}
+
+impl ZeroShotClassifier {
+    /// Encodes `labels` with the templates in `options` using the text encoder of `model`.
+    pub fn new<L: AsRef<str>>(
+        model: &Model,
+        labels: &[L],
+        options: &ZeroShotOptions,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            labels: labels.iter().map(|l| l.as_ref().to_owned()).collect(),
+            embeddings: label_embeddings(model, labels, &options.templates)?,
+            logit_scale: options.logit_scale,
+            text_params: *model.text_params(),
+            vision_params: *model.vision_params(),
+        })
+    }
+
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Normalized text embedding of every label, in label order.
+    pub fn embeddings(&self) -> &Embeddings {
+        &self.embeddings
+    }
+
+    pub fn logit_scale(&self) -> f32 {
+        self.logit_scale
+    }
+
+    pub fn text_params(&self) -> &TextParams {
+        &self.text_params
+    }
+
+    pub fn vision_params(&self) -> &VisionParams {
+        &self.vision_params
+    }
+
+    /// Fails with [`Error::ModelMismatch`] unless `model` has the hyperparameters the head was
+    /// built with.
+    pub fn check_model(&self, model: &Model) -> Result<(), Error> {
+        self.check_params(&model.params())
+    }
+
+    fn check_params(&self, model: &ModelParams) -> Result<(), Error> {
+        if self.text_params != model.text || self.vision_params != model.vision {
+            return Err(Error::ModelMismatch);
+        }
+        Ok(())
+    }
+
+    /// Ranks the labels by the softmax of their scaled cosine similarity to an image embedding,
+    /// returning them sorted by descending probability.
+    pub fn classify(&self, image: &Embedding) -> Result<Vec<LabelScore>, Error> {
+        if image.modality() != Modality::Image {
+            return Err(Error::Modality);
+        }
+        self.check_params(&ModelParams {
+            text: *image.text_params(),
+            vision: *image.vision_params(),
+        })?;
+
+        let mut logits = self.embeddings.cosine_similarity_to(image)? * self.logit_scale;
+        let max = logits.fold(f32::NEG_INFINITY, |m, v| m.max(*v));
+        logits.mapv_inplace(|v| (v - max).exp());
+        let total = logits.sum();
+
+        let mut scores = self
+            .labels
+            .iter()
+            .zip(logits)
+            .enumerate()
+            .map(|(index, (label, p))| LabelScore {
+                label: label.clone(),
+                index,
+                probability: p / total,
+            })
+            .collect::<Vec<_>>();
+        scores.sort_by(|a, b| b.probability.total_cmp(&a.probability));
+        Ok(scores)
+    }
+
+    /// Preprocesses and encodes `image` with `model` and classifies it.
+    pub fn classify_image<I: Image>(
+        &self,
+        model: &Model,
+        image: I,
+        options: &PreprocessOptions,
+    ) -> Result<Vec<LabelScore>, Error> {
+        self.check_model(model)?;
+        let blob = model.preprocess_image_with(image, options)?;
+        self.classify(&model.try_encode_image(&blob, true)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Serializes the head in a little-endian binary format.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let t = &self.text_params;
+        let v = &self.vision_params;
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        for value in [
+            t.vocab(),
+            t.positions(),
+            t.hidden_size(),
+            t.intermediate(),
+            t.projection_dim(),
+            t.head(),
+            t.layer(),
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&t.eps().to_le_bytes())?;
+        for value in [
+            v.image_size(),
+            v.patch_size(),
+            v.hidden_size(),
+            v.intermediate(),
+            v.projection_dim(),
+            v.head(),
+            v.layer(),
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&v.eps().to_le_bytes())?;
+
+        writer.write_all(&(self.embeddings.dim() as u32).to_le_bytes())?;
+        writer.write_all(&self.logit_scale.to_le_bytes())?;
+        writer.write_all(&(self.labels.len() as u32).to_le_bytes())?;
+        for label in &self.labels {
+            writer.write_all(&(label.len() as u32).to_le_bytes())?;
+            writer.write_all(label.as_bytes())?;
+        }
+        for value in self.embeddings.as_array() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Deserializes a head written by [`write_to`](Self::write_to).
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::InvalidClassifier("bad magic"));
+        }
+        if read_u32(&mut reader)? != VERSION {
+            return Err(Error::InvalidClassifier("unsupported version"));
+        }
+
+        let text_params = clip_cpp_sys::clip_text_hparams {
+            n_vocab: read_i32(&mut reader)?,
+            num_positions: read_i32(&mut reader)?,
+            hidden_size: read_i32(&mut reader)?,
+            n_intermediate: read_i32(&mut reader)?,
+            projection_dim: read_i32(&mut reader)?,
+            n_head: read_i32(&mut reader)?,
+            n_layer: read_i32(&mut reader)?,
+            eps: read_f32(&mut reader)?,
+        };
+        let vision_params = clip_cpp_sys::clip_vision_hparams {
+            image_size: read_i32(&mut reader)?,
+            patch_size: read_i32(&mut reader)?,
+            hidden_size: read_i32(&mut reader)?,
+            n_intermediate: read_i32(&mut reader)?,
+            projection_dim: read_i32(&mut reader)?,
+            n_head: read_i32(&mut reader)?,
+            n_layer: read_i32(&mut reader)?,
+            eps: read_f32(&mut reader)?,
+        };
+
+        let dim = read_u32(&mut reader)? as usize;
+        if dim != vision_params.projection_dim as usize {
+            return Err(Error::InvalidClassifier("projection dimension mismatch"));
+        }
+        let logit_scale = read_f32(&mut reader)?;
+        let n_labels = read_u32(&mut reader)? as usize;
+        let labels = (0..n_labels)
+            .map(|_| {
+                // Read through `take` so a corrupt length cannot request a huge allocation.
+                let len = read_u32(&mut reader)? as u64;
+                let mut label = Vec::new();
+                (&mut reader).take(len).read_to_end(&mut label)?;
+                if label.len() as u64 != len {
+                    return Err(Error::InvalidClassifier("truncated label"));
+                }
+                String::from_utf8(label).map_err(|_| Error::InvalidClassifier("label is not utf-8"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let n_values = n_labels
+            .checked_mul(dim)
+            .ok_or(Error::InvalidClassifier("too many embedding values"))?;
+        let embeddings = (0..n_values)
+            .map(|_| read_f32(&mut reader))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let model = ModelParams {
+            text: text_params.into(),
+            vision: vision_params.into(),
+        };
+        Ok(Self {
+            labels,
+            embeddings: Embeddings::new(
+                Array2::from_shape_vec((n_labels, dim), embeddings).unwrap(),
+                true,
+                Modality::Text,
+                model,
+            ),
+            logit_scale,
+            text_params: model.text,
+            vision_params: model.vision,
+        })
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, Error> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_i32<R: Read>(reader: &mut R) -> Result<i32, Error> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+fn read_f32<R: Read>(reader: &mut R) -> Result<f32, Error> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(f32::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classifier() -> ZeroShotClassifier {
+        let model = ModelParams::test(3, 224);
+        let embeddings = Array2::from_shape_vec((3, 3), vec![1., 0., 0., 0., 1., 0., 0., 0., 1.]);
+        ZeroShotClassifier {
+            labels: vec!["cat".to_owned(), "dog".to_owned(), "red apple".to_owned()],
+            embeddings: Embeddings::new(embeddings.unwrap(), true, Modality::Text, model),
+            logit_scale: DEFAULT_LOGIT_SCALE,
+            text_params: model.text,
+            vision_params: model.vision,
+        }
+    }
+
+    fn to_bytes(classifier: &ZeroShotClassifier) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        classifier.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn classifier_round_trips() {
+        let classifier = classifier();
+        let read = ZeroShotClassifier::read_from(&to_bytes(&classifier)[..]).unwrap();
+        assert_eq!(read, classifier);
+    }
+
+    #[test]
+    fn check_params_rejects_other_models() {
+        let classifier = classifier();
+        assert!(classifier.check_params(&ModelParams::test(3, 224)).is_ok());
+        assert!(matches!(
+            classifier.check_params(&ModelParams::test(3, 336)),
+            Err(Error::ModelMismatch)
+        ));
+
+        let read = ZeroShotClassifier::read_from(&to_bytes(&classifier)[..]).unwrap();
+        assert!(matches!(
+            read.check_params(&ModelParams::test(3, 336)),
+            Err(Error::ModelMismatch)
+        ));
+    }
+
+    #[test]
+    fn classify_ranks_labels_by_probability() {
+        let classifier = classifier();
+        let image = Embedding::new(
+            vec![0.1, 0.9, 0.0],
+            false,
+            Modality::Image,
+            ModelParams::test(3, 224),
+        );
+
+        let scores = classifier.classify(&image).unwrap();
+        let labels = scores.iter().map(LabelScore::label).collect::<Vec<_>>();
+        assert_eq!(labels, ["dog", "cat", "red apple"]);
+        assert_eq!(scores[0].index(), 1);
+        let total: f32 = scores.iter().map(LabelScore::probability).sum();
+        assert!((total - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn classify_rejects_other_embeddings() {
+        let classifier = classifier();
+        let text = Embedding::new(
+            vec![0., 1., 0.],
+            true,
+            Modality::Text,
+            ModelParams::test(3, 224),
+        );
+        assert!(matches!(classifier.classify(&text), Err(Error::Modality)));
+
+        let other = Embedding::new(
+            vec![0., 1., 0.],
+            true,
+            Modality::Image,
+            ModelParams::test(3, 336),
+        );
+        assert!(matches!(
+            classifier.classify(&other),
+            Err(Error::ModelMismatch)
+        ));
+    }
+
+    #[test]
+    fn read_from_rejects_corrupt_files() {
+        let bytes = to_bytes(&classifier());
+        for len in 0..bytes.len() {
+            assert!(ZeroShotClassifier::read_from(&bytes[..len]).is_err());
+        }
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            ZeroShotClassifier::read_from(&bad_magic[..]),
+            Err(Error::InvalidClassifier("bad magic"))
+        ));
+
+        // Magic, version, both hparam sets, dimension, logit scale and label count.
+        let first_label = 8 + 4 + 64 + 4 + 4 + 4;
+        let mut huge_label = bytes.clone();
+        huge_label[first_label..first_label + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            ZeroShotClassifier::read_from(&huge_label[..]),
+            Err(Error::InvalidClassifier("truncated label"))
+        ));
+    }
+}
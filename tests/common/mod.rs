@@ -0,0 +1,13 @@
+/// Model used by the tests that need one, from `CLIP_MODEL_PATH` or the default location.
+///
+/// Those tests are `#[ignore]`d and run with `cargo test -- --ignored`, so a missing model is an
+/// error rather than a silent pass.
+pub fn model_path() -> String {
+    let path = std::env::var("CLIP_MODEL_PATH")
+        .unwrap_or_else(|_| "./models/clip-vit-base-patch32_ggml-model-q4_1.gguf".to_owned());
+    assert!(
+        std::path::Path::new(&path).exists(),
+        "model not found at {path}, set CLIP_MODEL_PATH"
+    );
+    path
+}
@@ -0,0 +1,137 @@
+"""Generates the reference tensors checked by tests/preprocess_parity.rs.
+
+Requires numpy, Pillow and torchvision, and uses the `clip` package for the default pipeline
+when it is installed. Run from the repository root:
+
+    python tests/fixtures/preprocess/generate.py red_apple.jpg
+
+For every image, and for a synthetic odd-sized gradient, writes `<name>.input.npy` holding the
+`(height, width, 3)` RGB pixels decoded by Pillow and one `<name>.<mode>.<filter>.npy` per resize
+mode and filter holding the normalized `(224, 224, 3)` model input. The decoded pixels are stored
+so the Rust side does not depend on matching PIL's JPEG decoder. The library versions used are
+recorded in README.md next to the tensors.
+"""
+
+import sys
+from pathlib import Path
+
+import numpy as np
+import PIL
+import torchvision
+from PIL import Image
+from torchvision import transforms as T
+from torchvision.transforms import InterpolationMode
+from torchvision.transforms import functional as F
+
+SIZE = 224
+# OpenAI CLIP normalization, as stored in the converted ggml models.
+MEAN = np.array([0.48145466, 0.4578275, 0.40821073], dtype=np.float32)
+STD = np.array([0.26862954, 0.26130258, 0.27577711], dtype=np.float32)
+
+FILTERS = {
+    "nearest": InterpolationMode.NEAREST,
+    "bilinear": InterpolationMode.BILINEAR,
+    "bicubic": InterpolationMode.BICUBIC,
+}
+
+
+def center_crop(image, interpolation):
+    # The reference CLIP transform: Resize(224, BICUBIC) followed by CenterCrop(224).
+    return F.center_crop(F.resize(image, SIZE, interpolation=interpolation), SIZE)
+
+
+def squash(image, interpolation):
+    return F.resize(image, [SIZE, SIZE], interpolation=interpolation)
+
+
+def letterbox(image, interpolation):
+    width, height = image.size
+    long = max(width, height)
+    # Round half away from zero like `f32::round`, Python's `round` rounds half to even.
+    rw = min(max(int(np.floor(width * SIZE / long + 0.5)), 1), SIZE)
+    rh = min(max(int(np.floor(height * SIZE / long + 0.5)), 1), SIZE)
+    canvas = Image.new("RGB", (SIZE, SIZE), (0, 0, 0))
+    canvas.paste(F.resize(image, [rh, rw], interpolation=interpolation), ((SIZE - rw) // 2, (SIZE - rh) // 2))
+    return canvas
+
+
+MODES = {
+    "center_crop": center_crop,
+    "squash": squash,
+    "letterbox": letterbox,
+}
+
+
+def clip_transform():
+    """The preprocessing returned by `clip.load`, and its version.
+
+    Falls back to a copy of `clip.clip._transform` when the package is not installed.
+    """
+    try:
+        import clip
+
+        return clip.clip._transform(SIZE), getattr(clip, "__version__", "installed")
+    except ImportError:
+        transform = T.Compose(
+            [
+                T.Resize(SIZE, interpolation=InterpolationMode.BICUBIC),
+                T.CenterCrop(SIZE),
+                lambda image: image.convert("RGB"),
+                T.ToTensor(),
+                T.Normalize(MEAN.tolist(), STD.tolist()),
+            ]
+        )
+        return transform, None
+
+
+def gradient():
+    y, x = np.mgrid[0:23, 0:37]
+    pixels = np.stack([x * 255 // 36, y * 255 // 22, (x + y) * 255 // 58], axis=-1)
+    return Image.fromarray(pixels.astype(np.uint8), "RGB")
+
+
+def write_readme(out, paths, clip_version):
+    inputs = "".join(f"`{path}`, " for path in paths) + "a synthetic 37x23 gradient"
+    clip_line = f"clip {clip_version}" if clip_version else "clip not installed, `_transform` copied"
+    (out / "README.md").write_text(
+        f"""# Preprocessing reference tensors
+
+Written by `generate.py` from {inputs}. `*.input.npy` holds the
+RGB pixels decoded by Pillow, every other file the normalized `(224, 224, 3)` model input for one
+resize mode and filter. `*.center_crop.bicubic.npy` comes from the `clip.load` preprocessing.
+Rerun the script after upgrading any of the libraries below and commit the result.
+
+## Versions
+
+- Python {sys.version.split()[0]}
+- numpy {np.__version__}
+- Pillow {PIL.__version__}
+- torchvision {torchvision.__version__}
+- {clip_line}
+"""
+    )
+
+
+def main():
+    out = Path(__file__).parent
+    images = {Path(path).stem: Image.open(path).convert("RGB") for path in sys.argv[1:]}
+    images["gradient"] = gradient()
+    transform, clip_version = clip_transform()
+
+    for name, image in images.items():
+        np.save(out / f"{name}.input.npy", np.asarray(image, dtype=np.uint8))
+        for mode, resize in MODES.items():
+            for filter, interpolation in FILTERS.items():
+                if (mode, filter) == ("center_crop", "bicubic"):
+                    # CHW tensor from ToTensor, stored HWC like the others.
+                    tensor = transform(image).numpy().transpose(1, 2, 0)
+                else:
+                    pixels = np.asarray(resize(image, interpolation), dtype=np.float32) / 255.0
+                    tensor = (pixels - MEAN) / STD
+                np.save(out / f"{name}.{mode}.{filter}.npy", np.ascontiguousarray(tensor, dtype=np.float32))
+
+    write_readme(out, sys.argv[1:], clip_version)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,195 @@
+use std::path::{Path, PathBuf};
+
+use clip_cpp_rs as clip;
+
+/// Reference tensors written by `tests/fixtures/preprocess/generate.py` with Pillow and torchvision,
+/// see the README there for the versions.
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/preprocess")
+}
+
+const MODES: &[(&str, clip::ResizeMode)] = &[
+    ("center_crop", clip::ResizeMode::CenterCrop),
+    ("squash", clip::ResizeMode::Squash),
+    ("letterbox", clip::ResizeMode::Letterbox),
+];
+
+const FILTERS: &[(&str, clip::FilterType)] = &[
+    ("nearest", clip::FilterType::Nearest),
+    ("bilinear", clip::FilterType::Bilinear),
+    ("bicubic", clip::FilterType::Bicubic),
+];
+
+/// Side of the reference tensors.
+const SIZE: usize = 224;
+
+// Normalization the fixtures were generated with, written as in generate.py.
+const MEAN: [f32; 3] = [0.48145466, 0.4578275, 0.40821073];
+#[allow(clippy::excessive_precision)]
+const STD: [f32; 3] = [0.26862954, 0.26130258, 0.27577711];
+
+/// PIL resamples in fixed point, so single pixels may land one 8-bit step away from ours.
+const MAX_STEPS: f32 = 1.0;
+/// Average difference in 8-bit steps, catches systematic offsets that stay within `MAX_STEPS`.
+const MEAN_STEPS: f32 = 0.05;
+
+/// A little-endian, C-ordered array read from an `.npy` file.
+struct Npy {
+    shape: Vec<usize>,
+    descr: String,
+    data: Vec<u8>,
+}
+
+impl Npy {
+    fn read(path: &Path) -> Self {
+        let bytes = std::fs::read(path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+        assert_eq!(
+            &bytes[..6],
+            b"\x93NUMPY",
+            "{}: not an npy file",
+            path.display()
+        );
+        let (header_len, start) = match bytes[6] {
+            1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+            _ => (
+                u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize,
+                12,
+            ),
+        };
+        let header = std::str::from_utf8(&bytes[start..start + header_len]).unwrap();
+        let field = |key: &str| {
+            let at = header.find(&format!("'{key}':")).unwrap() + key.len() + 3;
+            header[at..].trim_start()
+        };
+
+        assert!(
+            field("fortran_order").starts_with("False"),
+            "{}: expected a C-ordered array",
+            path.display()
+        );
+        let descr = field("descr")[1..].split('\'').next().unwrap().to_owned();
+        let shape = field("shape");
+        let shape = shape[1..shape.find(')').unwrap()]
+            .split(',')
+            .filter(|d| !d.trim().is_empty())
+            .map(|d| d.trim().parse().unwrap())
+            .collect();
+
+        Self {
+            shape,
+            descr,
+            data: bytes[start + header_len..].to_vec(),
+        }
+    }
+
+    fn into_u8(self) -> (Vec<usize>, Vec<u8>) {
+        assert_eq!(self.descr, "|u1");
+        (self.shape, self.data)
+    }
+
+    fn into_f32(self) -> (Vec<usize>, Vec<f32>) {
+        assert_eq!(self.descr, "<f4");
+        let data = self
+            .data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        (self.shape, data)
+    }
+}
+
+/// Names of the fixture images.
+fn fixture_images() -> Vec<String> {
+    let mut names = std::fs::read_dir(fixtures())
+        .expect("Failed to list fixtures")
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            Some(name.strip_suffix(".input.npy")?.to_owned())
+        })
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(
+        names,
+        ["gradient", "red_apple"],
+        "missing fixtures, run tests/fixtures/preprocess/generate.py"
+    );
+    names
+}
+
+fn config(options: clip::PreprocessOptions) -> clip::PreprocessConfig {
+    clip::PreprocessConfig::new(SIZE as i32, MEAN, STD, options).expect("Failed to build config")
+}
+
+/// Asserts that `actual` matches the reference tensor at `path` within the tolerance.
+fn assert_matches(path: &Path, actual: &[f32], size: usize) {
+    let (shape, expected) = Npy::read(path).into_f32();
+    assert_eq!(shape, [size, size, 3], "{}", path.display());
+    assert_eq!(actual.len(), expected.len(), "{}", path.display());
+
+    let mut max = (0f32, 0);
+    let mut total = 0f32;
+    for (i, (a, e)) in actual.iter().zip(&expected).enumerate() {
+        let steps = (a - e).abs() * STD[i % 3] * 255.0;
+        total += steps;
+        if steps > max.0 {
+            max = (steps, i);
+        }
+    }
+    let mean = total / actual.len() as f32;
+    let (y, x, c) = (max.1 / 3 / size, max.1 / 3 % size, max.1 % 3);
+    assert!(
+        max.0 <= MAX_STEPS + 1e-3,
+        "{}: off by {:.2} steps at ({x}, {y}) channel {c}, expected {} found {}",
+        path.display(),
+        max.0,
+        expected[max.1],
+        actual[max.1],
+    );
+    assert!(
+        mean <= MEAN_STEPS,
+        "{}: off by {mean:.3} steps on average",
+        path.display()
+    );
+}
+
+#[test]
+#[ignore = "requires fixtures from tests/fixtures/preprocess/generate.py"]
+fn preprocess_matches_reference_tensors() {
+    for name in fixture_images() {
+        let (shape, pixels) = Npy::read(&fixtures().join(format!("{name}.input.npy"))).into_u8();
+        let image = clip::RGBImageView::new(shape[1] as u32, shape[0] as u32, &pixels)
+            .expect("Failed to wrap fixture image");
+
+        for (mode_name, mode) in MODES {
+            for (filter_name, filter) in FILTERS {
+                let path = fixtures().join(format!("{name}.{mode_name}.{filter_name}.npy"));
+                let options = clip::PreprocessOptions::default()
+                    .with_mode(*mode)
+                    .with_filter(*filter);
+                let blob = config(options)
+                    .preprocess_image(image)
+                    .expect("Failed to preprocess");
+                assert_matches(&path, blob.as_slice(), SIZE);
+            }
+        }
+    }
+}
+
+#[test]
+#[ignore = "requires fixtures from tests/fixtures/preprocess/generate.py"]
+fn default_preprocess_matches_reference_pipeline() {
+    let config = config(clip::PreprocessOptions::default());
+    for name in fixture_images() {
+        let (shape, pixels) = Npy::read(&fixtures().join(format!("{name}.input.npy"))).into_u8();
+        let image = clip::RGBImageView::new(shape[1] as u32, shape[0] as u32, &pixels)
+            .expect("Failed to wrap fixture image");
+        let blob = config
+            .preprocess_image(image)
+            .expect("Failed to preprocess");
+        assert_matches(
+            &fixtures().join(format!("{name}.center_crop.bicubic.npy")),
+            blob.as_slice(),
+            SIZE,
+        );
+    }
+}
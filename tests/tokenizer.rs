@@ -0,0 +1,88 @@
+use clip_cpp_rs as clip;
+
+mod common;
+
+use common::model_path;
+
+const PROMPTS: &[&str] = &[
+    "an apple",
+    "a photo of a dog",
+    "A Photo of a RED apple on a table.",
+    "a diagram of the solar system",
+    "two cats sleeping on a couch",
+    "it's a close-up photo of 3 birds",
+    "a picture of a car, a bus and a bike!!",
+    "  extra   whitespace\tand\nnewlines ",
+    "a caf\u{e9} in Z\u{fc}rich \u{1f600}",
+    "1024x768 @ 60Hz",
+];
+
+// Words made of lowercase in-vocabulary words, where the reference BPE and clip.cpp agree.
+const SIMPLE_PROMPTS: &[&str] = &[
+    "an apple",
+    "a photo of a dog",
+    "a photo of a red apple on a table",
+    "two cats sleeping on a couch",
+];
+
+#[test]
+#[ignore = "requires CLIP_MODEL_PATH"]
+fn encode_native_matches_clip_tokenize() {
+    let path = model_path();
+    let model = clip::Model::builder(&path)
+        .build()
+        .expect("Failed to build model");
+    let tokenizer = clip::Tokenizer::from_gguf(&path).expect("Failed to read tokenizer");
+
+    for prompt in PROMPTS {
+        let expected = model.tokenize(prompt).expect("Failed to tokenize");
+        let actual = tokenizer.encode_native(prompt).expect("Failed to tokenize");
+        assert_eq!(actual.ids(), expected.ids(), "prompt: {prompt:?}");
+    }
+}
+
+#[test]
+#[ignore = "requires CLIP_MODEL_PATH"]
+fn encode_matches_clip_tokenize_on_simple_prompts() {
+    let path = model_path();
+    let model = clip::Model::builder(&path)
+        .build()
+        .expect("Failed to build model");
+    let tokenizer = clip::Tokenizer::from_gguf(&path).expect("Failed to read tokenizer");
+
+    for prompt in SIMPLE_PROMPTS {
+        let expected = model.tokenize(prompt).expect("Failed to tokenize");
+        let actual = tokenizer.encode(prompt).expect("Failed to tokenize");
+        assert_eq!(actual.ids(), expected.ids(), "prompt: {prompt:?}");
+    }
+}
+
+#[test]
+#[ignore = "requires CLIP_MODEL_PATH"]
+fn tokenizer_round_trips() {
+    let path = model_path();
+    let tokenizer = clip::Tokenizer::from_gguf(&path).expect("Failed to read tokenizer");
+
+    for prompt in SIMPLE_PROMPTS {
+        let tokens = tokenizer.encode(prompt).expect("Failed to tokenize");
+        let text = tokenizer.decode(&tokens).expect("Failed to decode");
+        assert_eq!(
+            text.replace(' ', ""),
+            prompt.replace(' ', ""),
+            "prompt: {prompt:?}"
+        );
+    }
+}
+
+#[test]
+#[ignore = "requires CLIP_MODEL_PATH"]
+fn tokenizer_truncates_to_context_length() {
+    let path = model_path();
+    let tokenizer = clip::Tokenizer::from_gguf(&path).expect("Failed to read tokenizer");
+
+    let tokens = tokenizer
+        .encode("a ".repeat(200))
+        .expect("Failed to tokenize");
+    assert!(tokens.is_truncated());
+    assert_eq!(tokens.len(), tokenizer.context_length());
+}